@@ -0,0 +1,42 @@
+use crate::board::{Bitmap, Board, Color};
+use crate::eval::Score;
+use crate::piece::PieceType;
+use crate::r#move::Move;
+
+// Every piece left is a burden, and the pawns most of all as they are the
+// hardest to give away.
+const PIECE_PENALTY: Score = Score::new(60, 90);
+const PAWN_PENALTY: Score = Score::new(20, 40);
+
+impl Board {
+    /// The king is an ordinary piece that pawns may promote to, and taking
+    /// is compulsory.
+    pub fn generate_antichess_moves(&mut self) -> Vec<Move> {
+        let mut moves = self.generate_pseudo_legal_moves(Bitmap::MAX);
+
+        let king_promotions = moves
+            .iter()
+            .filter(|mov| mov.promotion == PieceType::Queen)
+            .map(|mov| Move::new(mov.start_square, mov.end_square, PieceType::King))
+            .collect::<Vec<_>>();
+        moves.extend(king_promotions);
+
+        if moves.iter().any(|mov| self.is_capture(mov)) {
+            moves.retain(|mov| self.is_capture(mov));
+        }
+        moves
+    }
+
+    /// The material that is still left to lose, from white's point of view.
+    pub fn antichess_score(&self) -> Score {
+        let count = |color: Color| {
+            let pieces = match color {
+                Color::White => self.white_pieces,
+                _ => self.black_pieces,
+            };
+            PIECE_PENALTY * pieces.count_ones() as i32
+                + PAWN_PENALTY * (pieces & self.pawns).count_ones() as i32
+        };
+        count(Color::Black) - count(Color::White)
+    }
+}
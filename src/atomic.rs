@@ -0,0 +1,108 @@
+use crate::board::{BitOperations, Bitmap, Board, Color, Square};
+use crate::piece::{Piece, PieceType};
+use crate::r#move::Move;
+
+impl Board {
+    fn pieces_of_color(&self, color: Color) -> Bitmap {
+        match color {
+            Color::White => self.white_pieces,
+            Color::Black => self.black_pieces,
+            Color::Empty => unreachable!(),
+        }
+    }
+
+    /// The pieces of `attacker` that attack `square` when `occupied` is
+    /// blocking the sliders.
+    pub fn attackers_to(&self, square: Square, attacker: Color, occupied: Bitmap) -> Bitmap {
+        let target = 1 << square;
+        let pieces = self.pieces_of_color(attacker);
+
+        let pawns = match attacker {
+            Color::White => Self::black_pawn_attacks(target),
+            _ => Self::white_pawn_attacks(target),
+        } & self.pawns;
+
+        (pawns
+            | (Self::knight_attacks(target) & self.knights)
+            | (Self::bishop_attacks(target, occupied, 0) & (self.bishops | self.queens))
+            | (Self::rook_attacks(target, occupied, 0) & (self.rooks | self.queens))
+            | (Self::king_attacks(target) & self.kings & !target))
+            & pieces
+    }
+
+    /// Whether the two kings stand next to each other.
+    pub fn kings_touch(&self) -> bool {
+        let white_king = self.white_pieces & self.kings;
+        let black_king = self.black_pieces & self.kings;
+        Self::king_attacks(white_king) & black_king > 0
+    }
+
+    /// Whether the king of `color` is in check by the atomic rules, where
+    /// touching the other king keeps it safe.
+    pub fn atomic_king_attacked(&self, color: Color) -> bool {
+        let king = self.pieces_of_color(color) & self.kings;
+        if king == 0 || self.kings_touch() {
+            return false;
+        }
+
+        let enemy = match color {
+            Color::White => Color::Black,
+            _ => Color::White,
+        };
+        self.attackers_to(king.lsb(), enemy, self.white_pieces | self.black_pieces) > 0
+    }
+
+    /// Blows up the piece that just captured on `square` and every piece
+    /// around it but the pawns, returning what was removed.
+    pub fn explode(&mut self, square: Square) -> Vec<(Square, Piece)> {
+        let occupied = self.white_pieces | self.black_pieces;
+        let mut radius =
+            ((Self::king_attacks(1 << square) & !self.pawns) | (1 << square)) & occupied;
+
+        let mut exploded = Vec::new();
+        while radius > 0 {
+            let square = radius.pop_lsb();
+            let piece = self.get_piece(square);
+            self.remove_piece(square);
+            match piece.typ {
+                PieceType::Rook => self.remove_castling_right(square),
+                PieceType::King => self.clear_castling_rights(piece.color),
+                _ => (),
+            }
+            exploded.push((square, piece));
+        }
+        exploded
+    }
+
+    /// Puts back the pieces of an explosion before the capture is unmade.
+    pub fn unexplode(&mut self, exploded: &[(Square, Piece)]) {
+        for (square, piece) in exploded.iter().rev() {
+            self.put_piece(*square, *piece);
+        }
+    }
+
+    /// Kings can't capture, and a move is legal when the own king survives
+    /// and is out of check or the other king is gone.
+    pub fn generate_atomic_moves(&mut self) -> Vec<Move> {
+        let color = self.turn;
+        let empty = !(self.white_pieces | self.black_pieces);
+        let mut moves = self.generate_pseudo_legal_moves(empty);
+
+        let king = self.own_pieces() & self.kings;
+        let enemy_king = self.enemy_pieces() & self.kings;
+        if king > 0 {
+            let attacked = self.generate_attack_bitboard() & !Self::king_attacks(enemy_king);
+            moves.append(&mut self.generate_castling_moves(king, attacked));
+        }
+
+        moves.retain(|mov| {
+            self.make_move(mov);
+            let own_king = self.enemy_pieces() & self.kings;
+            let enemy_king = self.own_pieces() & self.kings;
+            let legal = own_king > 0 && (enemy_king == 0 || !self.atomic_king_attacked(color));
+            self.unmake_move(mov);
+            legal
+        });
+        moves
+    }
+}
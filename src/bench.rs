@@ -0,0 +1,71 @@
+use crate::board::Board;
+use crate::options::Options;
+use crate::search::{SearchLimits, TranspositionTable};
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use std::time::Instant;
+
+pub const BENCH_DEPTH: u16 = 4;
+
+const BENCH_POSITIONS: [&str; 11] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "rnbqkb1r/pp1p1ppp/4pn2/2p5/2PP4/2N5/PP2PPPP/R1BQKBNR w KQkq - 0 4",
+    "r2q1rk1/pp2bppp/2n1pn2/3p4/3P4/2NBPN2/PP3PPP/R2Q1RK1 w - - 0 10",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "r3k2r/8/8/8/3pPp2/8/8/R3K1RR b KQkq e3 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/3N4 b - - 0 1",
+    "3b4/5kp1/1p1p1p1p/pP1PpP1P/P1P1P3/3KN3/8/8 w - - 0 1",
+    "8/5pk1/6p1/8/3R4/6P1/5PK1/2r5 w - - 0 40",
+    "8/8/8/8/5kp1/P7/8/1K1N4 w - - 0 1",
+];
+
+/// Searches a fixed set of positions to a fixed depth with default options
+/// and a fresh transposition table, so that the node count identifies the
+/// behaviour of the engine.
+pub fn bench(depth: u16) -> usize {
+    let options = Options::new();
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..Default::default()
+    };
+
+    let mut total_nodes = 0;
+    let start = Instant::now();
+
+    for (index, fen) in BENCH_POSITIONS.iter().enumerate() {
+        println!("\nPosition: {}/{} ({fen})", index + 1, BENCH_POSITIONS.len());
+
+        let mut board = Board::from_fen(fen.to_string());
+        let transposition_table = Arc::new(RwLock::new(TranspositionTable::new(
+            options.spin("Hash") as usize,
+        )));
+        let nodes = Arc::new(AtomicUsize::new(0));
+
+        board.search(
+            &limits,
+            &Arc::new(AtomicBool::new(false)),
+            &options,
+            &transposition_table,
+            &Arc::new(AtomicBool::new(false)),
+            &nodes,
+        );
+
+        total_nodes += nodes.load(Ordering::SeqCst);
+    }
+
+    let time = start.elapsed().as_millis();
+
+    println!("\n===========================");
+    println!("Total time (ms) : {time}");
+    println!("Nodes searched  : {total_nodes}");
+    println!(
+        "Nodes/second    : {}",
+        total_nodes as u128 * 1000 / u128::max(time, 1)
+    );
+
+    total_nodes
+}
@@ -1,8 +1,14 @@
+use crate::eval::Score;
+use crate::nnue::Accumulators;
 use crate::piece::*;
 use crate::r#move::*;
+use crate::variant::Variant;
 
 use const_for::const_for;
 
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
 use std::hash::{Hash, Hasher};
 
 pub type Square = i32;
@@ -80,21 +86,32 @@ pub enum Color {
     Empty,
 }
 
+/// The files of the rooks each side can still castle with, so that the
+/// rooks do not have to start in the corners as in Chess960.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CastlingRights {
-    pub white_king: bool,
-    pub white_queen: bool,
-    pub black_king: bool,
-    pub black_queen: bool,
+    pub white_king: Option<Square>,
+    pub white_queen: Option<Square>,
+    pub black_king: Option<Square>,
+    pub black_queen: Option<Square>,
 }
 
 impl CastlingRights {
     pub fn new() -> Self {
         CastlingRights {
-            white_king: false,
-            white_queen: false,
-            black_king: false,
-            black_queen: false,
+            white_king: None,
+            white_queen: None,
+            black_king: None,
+            black_queen: None,
+        }
+    }
+
+    pub fn standard() -> Self {
+        CastlingRights {
+            white_king: Some(7),
+            white_queen: Some(0),
+            black_king: Some(7),
+            black_queen: Some(0),
         }
     }
 }
@@ -106,6 +123,11 @@ pub struct Irreversible {
     pub half_move_clock: u8,
     pub full_move_clock: u8,
     pub captured_piece: Piece,
+    /// Whether the captured piece was a promoted pawn, for crazyhouse.
+    pub captured_promoted: bool,
+    pub castling: bool,
+    /// The pieces an atomic capture blew up, in the order they were removed.
+    pub exploded: Vec<(Square, Piece)>,
     pub mov: Move,
 }
 
@@ -123,14 +145,25 @@ pub enum ZobristPosition {
     BlackQueen = 640,
     BlackKing = 704,
 
-    SideToMove = 705,
+    SideToMove = 768,
 
-    WhiteKingCastle = 706,
-    WhiteQueenCastle = 707,
-    BlackKingCastle = 708,
-    BlackQueenCastle = 709,
+    WhiteKingCastle = 769,
+    WhiteQueenCastle = 770,
+    BlackKingCastle = 771,
+    BlackQueenCastle = 772,
 
-    EnPassant = 710,
+    EnPassant = 773,
+}
+
+const ZOBRIST_SEED: u64 = 0x4368657373207634;
+
+pub fn zobrist_keys() -> [u64; 781] {
+    let mut rng = StdRng::seed_from_u64(ZOBRIST_SEED);
+    let mut zobrist_array = [0; 781];
+    for key in zobrist_array.iter_mut() {
+        *key = rng.gen();
+    }
+    zobrist_array
 }
 
 const fn generate_rectangular() -> [[Bitmap; 64]; 64] {
@@ -175,6 +208,17 @@ pub struct Board {
 
     pub turn: Color,
     pub castling_rights: CastlingRights,
+    /// Whether moves are written the Chess960 way, castling as the king
+    /// taking its own rook.
+    pub chess960: bool,
+    pub variant: Variant,
+    /// The pieces each side has in hand in crazyhouse, white first and
+    /// indexed like `POCKET_PIECES`.
+    pub pockets: [[u8; 5]; 2],
+    /// Promoted pieces, which go back into the pocket as pawns.
+    pub promoted: Bitmap,
+    /// The checks given by white and by black in three-check.
+    pub checks: [u8; 2],
 
     pub half_move_clock: u8,
     pub full_move_clock: u8,
@@ -184,7 +228,14 @@ pub struct Board {
     pub fen: String,
 
     pub zobrist: u64,
+    /// Hash of the pawns alone, the key of the pawn hash table.
+    pub pawn_zobrist: u64,
     pub zobrist_array: [u64; 781],
+
+    pub psqt: Score,
+    pub phase: i32,
+
+    pub nnue: Option<Accumulators>,
 }
 
 impl Hash for Board {
@@ -195,10 +246,7 @@ impl Hash for Board {
 
 impl Board {
     pub fn new() -> Self {
-        let mut zobrist_array = [0; 781];
-        for i in 0..781 {
-            zobrist_array[i] = rand::random();
-        }
+        let zobrist_array = zobrist_keys();
 
         let mut board = Board {
             white_pieces: 0xFFFF,
@@ -211,7 +259,12 @@ impl Board {
             queens: 0x0800000000000008,
             kings: 0x1000000000000010,
 
-            castling_rights: CastlingRights::new(),
+            castling_rights: CastlingRights::standard(),
+            chess960: false,
+            variant: Variant::Standard,
+            pockets: [[0; 5]; 2],
+            promoted: 0,
+            checks: [0; 2],
             turn: Color::White,
             en_passant_target: -1,
             half_move_clock: 0,
@@ -222,19 +275,23 @@ impl Board {
 
             zobrist_array,
             zobrist: 0,
+            pawn_zobrist: 0,
+
+            psqt: Score::default(),
+            phase: 0,
+
+            nnue: None,
         };
 
         board.zobrist_init();
+        board.psqt_init();
+        board.nnue_init();
 
         board
     }
 
     pub fn empty_board() -> Self {
-        let mut zobrist_array = [0; 781];
-
-        for i in 0..781 {
-            zobrist_array[i] = rand::random();
-        }
+        let zobrist_array = zobrist_keys();
 
         let mut board = Board {
             white_pieces: 0,
@@ -248,6 +305,11 @@ impl Board {
             kings: 0,
 
             castling_rights: CastlingRights::new(),
+            chess960: false,
+            variant: Variant::Standard,
+            pockets: [[0; 5]; 2],
+            promoted: 0,
+            checks: [0; 2],
             turn: Color::White,
             en_passant_target: -1,
             half_move_clock: 0,
@@ -258,9 +320,17 @@ impl Board {
 
             zobrist_array,
             zobrist: 0,
+            pawn_zobrist: 0,
+
+            psqt: Score::default(),
+            phase: 0,
+
+            nnue: None,
         };
 
         board.zobrist_init();
+        board.psqt_init();
+        board.nnue_init();
 
         board
     }
@@ -306,10 +376,25 @@ impl Board {
             Color::Empty => unreachable!(),
         }
 
+        if self.castling_rights.white_king.is_some() {
+            self.zobrist ^= self.zobrist_array[ZobristPosition::WhiteKingCastle as usize];
+        }
+        if self.castling_rights.white_queen.is_some() {
+            self.zobrist ^= self.zobrist_array[ZobristPosition::WhiteQueenCastle as usize];
+        }
+        if self.castling_rights.black_king.is_some() {
+            self.zobrist ^= self.zobrist_array[ZobristPosition::BlackKingCastle as usize];
+        }
+        if self.castling_rights.black_queen.is_some() {
+            self.zobrist ^= self.zobrist_array[ZobristPosition::BlackQueenCastle as usize];
+        }
+
         if self.en_passant_target != -1 {
             self.zobrist ^= self.zobrist_array
                 [ZobristPosition::EnPassant as usize + self.en_passant_target.file() as usize];
         }
+
+        self.zobrist ^= self.pocket_zobrist() ^ self.check_zobrist();
     }
 
     pub fn zobrist_change_square(&mut self, square: Square) {
@@ -317,8 +402,10 @@ impl Board {
         match color {
             Color::White => match typ {
                 PieceType::Pawn => {
-                    self.zobrist ^=
-                        self.zobrist_array[ZobristPosition::WhitePawn as usize + square as usize]
+                    let key =
+                        self.zobrist_array[ZobristPosition::WhitePawn as usize + square as usize];
+                    self.zobrist ^= key;
+                    self.pawn_zobrist ^= key;
                 }
                 PieceType::Knight => {
                     self.zobrist ^=
@@ -344,8 +431,10 @@ impl Board {
             },
             Color::Black => match typ {
                 PieceType::Pawn => {
-                    self.zobrist ^=
-                        self.zobrist_array[ZobristPosition::BlackPawn as usize + square as usize]
+                    let key =
+                        self.zobrist_array[ZobristPosition::BlackPawn as usize + square as usize];
+                    self.zobrist ^= key;
+                    self.pawn_zobrist ^= key;
                 }
                 PieceType::Knight => {
                     self.zobrist ^=
@@ -493,22 +582,67 @@ impl Board {
         )
     }
 
+    /// Reads a fen that is known to be valid, like the fens of the engine
+    /// itself.
     pub fn from_fen(fen: String) -> Self {
+        Board::try_from_fen(&fen).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Reads `fen`, or says why it is not a valid fen.
+    pub fn try_from_fen(fen: &str) -> Result<Self, String> {
+        let fen = fen.split_whitespace().collect::<Vec<_>>().join(" ");
+        let invalid = |reason: &str| format!("\"{fen}\" is not a valid fen: {reason}!");
+
         let mut board = Board::empty_board();
         board.fen = fen.clone();
 
-        let mut parts = fen.split(" ");
-        let pieces = parts.next().unwrap();
-        let turn = parts.next().unwrap();
-        let castling = parts.next().unwrap();
-        let en_passant = parts.next().unwrap();
-        let halfmove_clock = parts.next().unwrap();
-        let fullmove_clock = parts.next().unwrap();
+        let mut parts = fen.split(' ');
+        let (Some(pieces), Some(turn), Some(castling), Some(en_passant)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("it needs at least four fields"));
+        };
+        // Three-check adds the checks left after the en passant square or
+        // the checks given at the end.
+        let mut parts = parts.peekable();
+        let checks_left = parts.next_if(|part| part.contains('+'));
+        let halfmove_clock = parts.next().unwrap_or("0");
+        let fullmove_clock = parts.next().unwrap_or("1");
+        let checks_given = parts.next();
         let mut pos: Square = 56;
+        let mut last: Square = 56;
+
+        // Crazyhouse adds the pockets, either in brackets or as a ninth rank.
+        let (pieces, pocket) = match pieces.split_once('[') {
+            Some((pieces, pocket)) => (pieces, Some(pocket.trim_end_matches(']'))),
+            None if pieces.matches('/').count() == 8 => {
+                let (pieces, pocket) = pieces.rsplit_once('/').unwrap();
+                (pieces, Some(pocket))
+            }
+            None => (pieces, None),
+        };
+
+        let ranks = pieces.split('/').collect::<Vec<_>>();
+        let width = |rank: &str| {
+            rank.chars().try_fold(0, |width, piece| match piece {
+                '1'..='8' => Some(width + piece as u32 - '0' as u32),
+                '~' => Some(width),
+                'P' | 'N' | 'B' | 'R' | 'Q' | 'K' | 'p' | 'n' | 'b' | 'r' | 'q' | 'k' => {
+                    Some(width + 1)
+                }
+                _ => None,
+            })
+        };
+        if ranks.len() != 8 || ranks.iter().any(|rank| width(rank) != Some(8)) {
+            return Err(invalid("the pieces need eight ranks of eight squares"));
+        }
 
         for piece in pieces.chars() {
             if piece == '/' {
                 continue;
+            } else if piece == '~' {
+                board.promoted |= 1 << last;
+                continue;
             } else if piece.is_digit(10) {
                 pos += piece as Square - '0' as Square;
             } else {
@@ -535,6 +669,7 @@ impl Board {
                         _ => (),
                     }
                 }
+                last = pos;
                 pos += 1;
             }
             if pos > 8 && pos % 8 == 0 {
@@ -545,30 +680,189 @@ impl Board {
         match turn {
             "w" => board.turn = Color::White,
             "b" => board.turn = Color::Black,
-            _ => panic!("Fen needs a turn!"),
+            _ => return Err(invalid("the side to move is w or b")),
         }
 
+        // X-FEN writes K and Q for the outermost rooks, Shredder-FEN the
+        // files of the rooks.
         for castling_right in castling.chars() {
-            match castling_right {
-                'K' => board.castling_rights.white_king = true,
-                'Q' => board.castling_rights.white_queen = true,
-                'k' => board.castling_rights.black_king = true,
-                'q' => board.castling_rights.black_queen = true,
-                _ => (),
+            if castling_right == '-' {
+                continue;
+            }
+            let (color, rank) = match castling_right.is_uppercase() {
+                true => (Color::White, 0),
+                false => (Color::Black, 56),
+            };
+            let own = match color {
+                Color::White => board.white_pieces,
+                _ => board.black_pieces,
+            };
+            let king_file = (board.kings & own).lsb() - rank;
+            let rooks = (0..8).filter(|file| board.rooks & own & (1 << (rank + file)) > 0);
+
+            let file = match castling_right.to_ascii_lowercase() {
+                'k' => rooks.filter(|file| *file > king_file).max(),
+                'q' => rooks.filter(|file| *file < king_file).min(),
+                file @ 'a'..='h' => Some(file as Square - 'a' as Square),
+                _ => return Err(invalid("the castling rights are -, KQkq or files")),
+            };
+            // Without a king on its first rank a side can't castle.
+            let Some(file) = file.filter(|_| (0..8).contains(&king_file)) else {
+                continue;
+            };
+
+            match (color, file > king_file) {
+                (Color::White, true) => board.castling_rights.white_king = Some(file),
+                (Color::White, false) => board.castling_rights.white_queen = Some(file),
+                (_, true) => board.castling_rights.black_king = Some(file),
+                (_, false) => board.castling_rights.black_queen = Some(file),
             }
         }
 
-        if en_passant != "-" {
-            board.en_passant_target = en_passant.to_string().to_square();
-        } else {
-            board.en_passant_target = -1;
+        board.en_passant_target = match en_passant.as_bytes() {
+            b"-" => -1,
+            [b'a'..=b'h', b'1'..=b'8'] => en_passant.to_string().to_square(),
+            _ => return Err(invalid("the en passant square is - or a square")),
+        };
+
+        let (Ok(halfmove_clock), Ok(fullmove_clock)) =
+            (halfmove_clock.parse(), fullmove_clock.parse())
+        else {
+            return Err(invalid("the move clocks are numbers"));
+        };
+        board.half_move_clock = halfmove_clock;
+        board.full_move_clock = fullmove_clock;
+
+        if let Some(pocket) = pocket {
+            board.variant = Variant::Crazyhouse;
+            board.parse_pocket(pocket);
         }
 
-        board.half_move_clock = halfmove_clock.parse().unwrap();
-        board.full_move_clock = fullmove_clock.parse().unwrap();
+        if let Some(checks) = checks_left.or(checks_given) {
+            board.variant = Variant::ThreeCheck;
+            board.parse_checks(checks);
+        }
 
         board.zobrist_init();
+        board.psqt_init();
+        board.nnue_init();
 
-        board
+        Ok(board)
+    }
+
+    /// The castling field of the fen, in X-FEN: `K` and `Q` when the rook is
+    /// the outermost one on its side of the king, its file otherwise.
+    fn castling_fen(&self) -> String {
+        let mut result = String::new();
+        for (color, rank, rights) in [
+            (
+                Color::White,
+                0,
+                [
+                    self.castling_rights.white_king,
+                    self.castling_rights.white_queen,
+                ],
+            ),
+            (
+                Color::Black,
+                56,
+                [
+                    self.castling_rights.black_king,
+                    self.castling_rights.black_queen,
+                ],
+            ),
+        ] {
+            let own = match color {
+                Color::White => self.white_pieces,
+                _ => self.black_pieces,
+            };
+            let king_file = (self.kings & own).lsb() - rank;
+            let rooks = (0..8)
+                .filter(|file| self.rooks & own & (1 << (rank + file)) > 0)
+                .collect::<Vec<_>>();
+
+            for (right, outermost, letter) in [
+                (
+                    rights[0],
+                    rooks.iter().filter(|file| **file > king_file).max(),
+                    'K',
+                ),
+                (
+                    rights[1],
+                    rooks.iter().filter(|file| **file < king_file).min(),
+                    'Q',
+                ),
+            ] {
+                let Some(file) = right else {
+                    continue;
+                };
+                let letter = match Some(&file) == outermost {
+                    true => letter,
+                    false => (b'A' + file as u8) as char,
+                };
+                result.push(match color {
+                    Color::White => letter,
+                    _ => letter.to_ascii_lowercase(),
+                });
+            }
+        }
+
+        match result.is_empty() {
+            true => "-".into(),
+            false => result,
+        }
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut pieces = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let piece = self.get_piece(rank * 8 + file);
+                if piece.typ == PieceType::Empty {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    pieces.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                pieces.push(Self::converter(piece));
+                if self.promoted & (1 << (rank * 8 + file)) > 0 {
+                    pieces.push('~');
+                }
+            }
+            if empty > 0 {
+                pieces.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                pieces.push('/');
+            }
+        }
+
+        if self.variant == Variant::Crazyhouse {
+            pieces.push_str(&format!("[{}]", self.pocket_fen()));
+        }
+
+        let turn = match self.turn {
+            Color::White => "w",
+            _ => "b",
+        };
+        let en_passant = match self.en_passant_target {
+            -1 => "-".into(),
+            square => square.as_string(),
+        };
+
+        let en_passant = match self.variant {
+            Variant::ThreeCheck => format!("{en_passant} {}", self.checks_fen()),
+            _ => en_passant,
+        };
+
+        format!(
+            "{pieces} {turn} {} {en_passant} {} {}",
+            self.castling_fen(),
+            self.half_move_clock,
+            self.full_move_clock
+        )
     }
 }
@@ -1,6 +1,7 @@
 use crate::board::{Bitmap, Board, Color};
 use crate::piece::PieceType;
 use crate::r#move::Move;
+use crate::variant::Variant;
 
 impl Board {
     pub fn is_checking_move(&self, mov: &Move) -> bool {
@@ -8,7 +9,12 @@ impl Board {
         let occupied = self.white_pieces | self.black_pieces;
         let king = self.enemy_pieces() & self.kings;
 
-        match self.get_piece(mov.start_square).typ {
+        let typ = match mov.is_drop() {
+            true => mov.promotion,
+            false => self.get_piece(mov.start_square).typ,
+        };
+
+        match typ {
             PieceType::Pawn => match self.turn {
                 Color::White => Self::black_pawn_attacks(king) & (1 << mov.end_square) > 0,
                 Color::Black => Self::black_pawn_attacks(king) & (1 << mov.end_square) > 0,
@@ -29,6 +35,14 @@ impl Board {
     }
 
     pub fn get_checkers(&self) -> Bitmap {
+        // Antichess has no check, and in atomic a king touching the other
+        // one can't be taken without blowing up both.
+        match self.variant {
+            Variant::Antichess => return 0,
+            Variant::Atomic if self.kings_touch() => return 0,
+            _ => (),
+        }
+
         let enemy_pieces = self.enemy_pieces();
         let own_pieces = self.own_pieces();
         let occupied = self.white_pieces | self.black_pieces;
@@ -0,0 +1,408 @@
+use crate::bench::{bench, BENCH_DEPTH};
+use crate::board::Board;
+use crate::epd::{run_epd, EPD_MOVETIME};
+use crate::options::Options;
+use crate::perft::perft_suite;
+use crate::pgn_to_fen::convert_pgn_from_file;
+use crate::polyglot::{build_book, BOOK_OUTPUT, BOOK_PLIES};
+use crate::r#move::Move;
+use crate::search::{SearchLimits, TranspositionTable};
+use crate::selfplay::{
+    read_openings, run_match, MatchSettings, PlayerSettings, DRAW_SCORE, RESIGN_SCORE,
+    SELFPLAY_GAMES, SELFPLAY_MOVETIME, SELFPLAY_PGN,
+};
+use crate::server::{serve, SERVE_PORT};
+use crate::sprt::{Sprt, SprtModel, SPRT_ALPHA, SPRT_BETA, SPRT_GAMES};
+use crate::tune::{tune, TUNE_ITERATIONS, TUNE_OUTPUT};
+use crate::variant::Variant;
+
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::{Arc, RwLock};
+
+/// `analyse` searches this deep when it is given neither a depth nor a
+/// movetime.
+const ANALYSE_DEPTH: u16 = 8;
+
+const USAGE: &str = "\
+usage: chess_v4 [command] [arguments]
+
+Without a command the engine speaks UCI, or xboard, on stdin.
+
+commands:
+  uci                                 speak UCI or xboard on stdin
+  perft [<fen> <depth>]               count the leaf nodes, or run the perft suite
+  divide <fen> <depth>                count the leaf nodes below every move
+  perft-test <file> <depth>           compare perft with stockfish on the fens of a file
+  bench [<depth>]                     search the bench positions
+  analyse <fen> [--depth <depth>] [--movetime <ms>] [--multipv <lines>]
+                                      search a position and print the best move
+  epd <file> [--depth <depth>] [--movetime <ms>] [--jobs <threads>]
+                                      run a test suite of bm/am positions
+  pgn-to-fen <file>                   write the positions of a pgn file to fens.txt
+  tune <file> [<iterations>] [<output>]
+                                      tune the evaluation on labelled positions
+  book <pgn> [<output>] [<plies>]     build a polyglot book from a pgn file
+  serve [--port <port>]               answer analysis requests over HTTP
+  selfplay [--games <games>] [--openings <file>] [--depth <depth>] [--movetime <ms>]
+           [--first-options <options>] [--second-options <options>]
+           [--first-engine <path>] [--second-engine <path>] [--variant <variant>]
+           [--pgn <file>] [--resign <cp>] [--draw <cp>]
+           [--sprt <elo0>,<elo1>] [--alpha <alpha>] [--beta <beta>] [--model <model>]
+                                      play a match between two engine configurations
+  match                               the same as selfplay
+  help                                print this message
+
+A <fen> is quoted as one argument, or is startpos. The <options> of a player are
+UCI options like \"Hash=32,Threads=2\"; an engine path plays an external UCI engine.
+With --sprt the match stops once the test is decided, the <model> being pentanomial
+or trinomial.";
+
+/// The exit code of a command given the wrong arguments.
+const USAGE_ERROR: u8 = 2;
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("{message}\n\n{USAGE}");
+    ExitCode::from(USAGE_ERROR)
+}
+
+fn failure(message: &str) -> ExitCode {
+    eprintln!("{message}");
+    ExitCode::FAILURE
+}
+
+/// Parses the argument called `name`, which has to be given.
+fn parse<T: FromStr>(argument: Option<&String>, name: &str) -> Result<T, String> {
+    match argument {
+        Some(argument) => argument
+            .parse()
+            .map_err(|_| format!("\"{argument}\" is not a valid {name}!")),
+        None => Err(format!("missing {name}!")),
+    }
+}
+
+/// Parses the value following `--flag`, if the flag is given.
+fn parse_flag<T: FromStr>(args: &[String], flag: &str) -> Result<Option<T>, String> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(index) => parse(args.get(index + 1), &flag[2..]).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn parse_board(fen: Option<&String>) -> Result<Board, String> {
+    let board = match fen.map(String::as_str) {
+        Some("startpos") => Board::new(),
+        Some(fen) => Board::try_from_fen(fen)?,
+        None => return Err("missing fen!".to_string()),
+    };
+    if !board.has_kings() {
+        return Err("the fen needs a king of each color!".to_string());
+    }
+    Ok(board)
+}
+
+/// Counts the leaf nodes of `fen`, printing them per move if `divide`.
+fn perft(args: &[String], divide: bool) -> ExitCode {
+    let (mut board, depth) = match (parse_board(args.first()), parse(args.get(1), "depth")) {
+        (Ok(board), Ok(depth)) => (board, depth),
+        (Err(error), _) | (_, Err(error)) => return usage_error(&error),
+    };
+
+    let result = board.perft(depth);
+    if divide {
+        let mut moves = result.iter().collect::<Vec<_>>();
+        moves.sort();
+        for (mov, count) in moves {
+            println!("{mov}: {count}");
+        }
+        println!();
+    }
+    println!(
+        "Nodes searched: {}",
+        result.values().map(|&nodes| nodes as u64).sum::<u64>()
+    );
+    ExitCode::SUCCESS
+}
+
+fn analyse(args: &[String]) -> ExitCode {
+    let mut board = match parse_board(args.first()) {
+        Ok(board) => board,
+        Err(error) => return usage_error(&error),
+    };
+    let flags = (
+        parse_flag(args, "--depth"),
+        parse_flag(args, "--movetime"),
+        parse_flag::<i64>(args, "--multipv"),
+    );
+    let (depth, movetime, multi_pv) = match flags {
+        (Ok(depth), Ok(movetime), Ok(multi_pv)) => (depth, movetime, multi_pv),
+        (Err(error), _, _) | (_, Err(error), _) | (_, _, Err(error)) => return usage_error(&error),
+    };
+
+    let mut options = Options::new();
+    if let Some(multi_pv) = multi_pv {
+        if let Err(error) = options.set("MultiPV", &multi_pv.to_string()) {
+            return usage_error(&error);
+        }
+    }
+    let limits = SearchLimits {
+        depth: depth.or(movetime.map_or(Some(ANALYSE_DEPTH), |_| None)),
+        movetime,
+        ..Default::default()
+    };
+
+    let transposition_table = Arc::new(RwLock::new(TranspositionTable::new(
+        options.spin("Hash") as usize
+    )));
+    let result = board.search(
+        &limits,
+        &Arc::new(AtomicBool::new(false)),
+        &options,
+        &transposition_table,
+        &Arc::new(AtomicBool::new(false)),
+        &Arc::new(AtomicUsize::new(0)),
+    );
+
+    match result.first() {
+        Some(best) if best.mov != Move::null() => {
+            println!("bestmove {}", board.move_to_uci(&best.mov));
+            ExitCode::SUCCESS
+        }
+        _ => failure("the position has no legal moves"),
+    }
+}
+
+fn epd(args: &[String]) -> ExitCode {
+    let path = match args.first() {
+        Some(path) => path,
+        None => return usage_error("missing epd file!"),
+    };
+    let flags = (
+        parse_flag(args, "--depth"),
+        parse_flag(args, "--movetime"),
+        parse_flag::<usize>(args, "--jobs"),
+    );
+    let (depth, movetime, jobs) = match flags {
+        (Ok(depth), Ok(movetime), Ok(jobs)) => (depth, movetime, jobs),
+        (Err(error), _, _) | (_, Err(error), _) | (_, _, Err(error)) => return usage_error(&error),
+    };
+
+    let limits = SearchLimits {
+        depth,
+        movetime: movetime.or(depth.map_or(Some(EPD_MOVETIME), |_| None)),
+        ..Default::default()
+    };
+    match run_epd(path, limits, jobs.unwrap_or(1).max(1)) {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+/// Reads UCI options written as `Hash=32,Threads=2`.
+fn parse_options(options: Option<String>) -> Result<Vec<(String, String)>, String> {
+    let options = match options {
+        Some(options) => options,
+        None => return Ok(Vec::new()),
+    };
+    options
+        .split(',')
+        .filter(|option| !option.trim().is_empty())
+        .map(|option| match option.split_once('=') {
+            Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+            None => Err(format!(
+                "\"{option}\" is not a valid option, use Name=Value!"
+            )),
+        })
+        .collect()
+}
+
+/// Reads the hypotheses and error rates of `--sprt <elo0>,<elo1>`.
+fn parse_sprt(args: &[String]) -> Result<Option<Sprt>, String> {
+    let hypotheses = match parse_flag::<String>(args, "--sprt")? {
+        Some(hypotheses) => hypotheses,
+        None => return Ok(None),
+    };
+    let (elo0, elo1) = match hypotheses.split_once(',') {
+        Some((elo0, elo1)) => (
+            parse(Some(&elo0.trim().to_string()), "elo0")?,
+            parse(Some(&elo1.trim().to_string()), "elo1")?,
+        ),
+        None => return Err(format!("\"{hypotheses}\" is not a valid elo0,elo1!")),
+    };
+    let model = match parse_flag::<String>(args, "--model")? {
+        Some(name) => match SprtModel::from_name(&name) {
+            Some(model) => model,
+            None => return Err(format!("\"{name}\" is not a valid model!")),
+        },
+        None => SprtModel::Pentanomial,
+    };
+
+    let sprt = Sprt {
+        elo0,
+        elo1,
+        alpha: parse_flag(args, "--alpha")?.unwrap_or(SPRT_ALPHA),
+        beta: parse_flag(args, "--beta")?.unwrap_or(SPRT_BETA),
+        model,
+    };
+    if elo0 >= elo1 {
+        return Err("elo0 has to be below elo1!".to_string());
+    }
+    let valid = |rate: f64| rate > 0.0 && rate < 0.5;
+    if !valid(sprt.alpha) || !valid(sprt.beta) {
+        return Err("alpha and beta have to be between 0 and 0.5!".to_string());
+    }
+    Ok(Some(sprt))
+}
+
+fn selfplay_settings(args: &[String]) -> Result<MatchSettings, String> {
+    let depth = parse_flag(args, "--depth")?;
+    let movetime = parse_flag(args, "--movetime")?;
+    let openings = match parse_flag::<String>(args, "--openings")? {
+        Some(path) => read_openings(&path)?,
+        None => Vec::new(),
+    };
+    let variant = match parse_flag::<String>(args, "--variant")? {
+        Some(name) if Variant::from_name(&name).name() != name.to_ascii_lowercase() => {
+            return Err(format!("\"{name}\" is not a variant!"))
+        }
+        Some(name) => Variant::from_name(&name),
+        None => Variant::Standard,
+    };
+
+    let player = |side: &str, name: &str| -> Result<PlayerSettings, String> {
+        let engine = parse_flag::<String>(args, &format!("--{side}-engine"))?;
+        Ok(PlayerSettings {
+            name: match engine {
+                Some(_) => String::new(),
+                None => name.to_string(),
+            },
+            options: parse_options(parse_flag(args, &format!("--{side}-options"))?)?,
+            engine,
+        })
+    };
+
+    let sprt = parse_sprt(args)?;
+    let games = match sprt {
+        Some(_) => SPRT_GAMES,
+        None => SELFPLAY_GAMES,
+    };
+
+    Ok(MatchSettings {
+        games: parse_flag(args, "--games")?.unwrap_or(games),
+        openings,
+        limits: SearchLimits {
+            depth,
+            movetime: movetime.or(depth.map_or(Some(SELFPLAY_MOVETIME), |_| None)),
+            ..Default::default()
+        },
+        variant,
+        first: player("first", "chess_v4")?,
+        second: player("second", "chess_v4 (2)")?,
+        pgn: parse_flag(args, "--pgn")?.unwrap_or(SELFPLAY_PGN.to_string()),
+        resign_score: parse_flag(args, "--resign")?.unwrap_or(RESIGN_SCORE),
+        draw_score: parse_flag(args, "--draw")?.unwrap_or(DRAW_SCORE),
+        sprt,
+    })
+}
+
+/// Runs the command of `args`, which holds the arguments after the name of
+/// the program.
+pub fn run(args: &[String]) -> ExitCode {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => return usage_error("missing command!"),
+    };
+
+    match command {
+        "perft" if args.is_empty() => match perft_suite() {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::FAILURE,
+        },
+        "perft" => perft(args, false),
+        "divide" => perft(args, true),
+        "perft-test" => match (args.first(), parse(args.get(1), "depth")) {
+            (Some(file), Ok(depth)) => {
+                Board::run_perft_multi_test(file, depth);
+                ExitCode::SUCCESS
+            }
+            (None, _) => usage_error("missing file!"),
+            (_, Err(error)) => usage_error(&error),
+        },
+        "bench" => {
+            let depth = match args.first() {
+                Some(_) => match parse(args.first(), "depth") {
+                    Ok(depth) => depth,
+                    Err(error) => return usage_error(&error),
+                },
+                None => BENCH_DEPTH,
+            };
+            bench(depth);
+            ExitCode::SUCCESS
+        }
+        "analyse" => analyse(args),
+        "epd" => epd(args),
+        "pgn-to-fen" => match args.first() {
+            Some(file) => match convert_pgn_from_file(file) {
+                true => ExitCode::SUCCESS,
+                false => ExitCode::FAILURE,
+            },
+            None => usage_error("missing pgn file!"),
+        },
+        "tune" => {
+            let path = match args.first() {
+                Some(path) => path,
+                None => return usage_error("tune needs a file of labelled positions!"),
+            };
+            let iterations = match args.get(1) {
+                Some(_) => match parse(args.get(1), "number of iterations") {
+                    Ok(iterations) => iterations,
+                    Err(error) => return usage_error(&error),
+                },
+                None => TUNE_ITERATIONS,
+            };
+            let output = args.get(2).map_or(TUNE_OUTPUT, String::as_str);
+            match tune(path, iterations, output) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(error) => failure(&error),
+            }
+        }
+        "book" => {
+            let path = match args.first() {
+                Some(path) => path,
+                None => return usage_error("book needs a pgn file!"),
+            };
+            let output = args.get(1).map_or(BOOK_OUTPUT, String::as_str);
+            let plies = match args.get(2) {
+                Some(_) => match parse(args.get(2), "number of plies") {
+                    Ok(plies) => plies,
+                    Err(error) => return usage_error(&error),
+                },
+                None => BOOK_PLIES,
+            };
+            match build_book(path, output, plies) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(error) => failure(&error),
+            }
+        }
+        "serve" => match parse_flag(args, "--port") {
+            Ok(port) => match serve(port.unwrap_or(SERVE_PORT)) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(error) => failure(&error),
+            },
+            Err(error) => usage_error(&error),
+        },
+        "selfplay" | "match" => match selfplay_settings(args) {
+            Ok(settings) => match run_match(&settings) {
+                Ok(_) => ExitCode::SUCCESS,
+                Err(error) => failure(&error),
+            },
+            Err(error) => usage_error(&error),
+        },
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        command => usage_error(&format!("\"{command}\" is not a command!")),
+    }
+}
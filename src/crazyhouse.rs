@@ -0,0 +1,214 @@
+use crate::board::{BitOperations, Bitmap, Board, Color, Irreversible};
+use crate::eval::Score;
+use crate::piece::{Piece, PieceType};
+use crate::psqt::{piece_index, MATERIAL};
+use crate::r#move::Move;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::sync::OnceLock;
+
+/// The pieces that can be held in a pocket, in the order of `Board::pockets`.
+pub const POCKET_PIECES: [PieceType; 5] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+];
+
+/// No side can hold more than 16 pieces of one kind.
+const MAX_POCKET: usize = 16;
+
+const POCKET_SEED: u64 = 0x506F636B657473;
+
+// Pawns can't be dropped on the first or last rank.
+const PAWN_DROP_SQUARES: Bitmap = 0x00FFFFFFFFFFFF00;
+
+/// Zobrist keys for holding one more piece of a kind, by colour, piece and
+/// how many were held before. They are kept out of `zobrist_array` so that
+/// boards stay cheap to clone.
+fn pocket_keys() -> &'static [[[u64; MAX_POCKET]; 5]; 2] {
+    static POCKET_KEYS: OnceLock<[[[u64; MAX_POCKET]; 5]; 2]> = OnceLock::new();
+    POCKET_KEYS.get_or_init(|| {
+        let mut rng = StdRng::seed_from_u64(POCKET_SEED);
+        let mut keys = [[[0; MAX_POCKET]; 5]; 2];
+        for key in keys.iter_mut().flatten().flatten() {
+            *key = rng.gen();
+        }
+        keys
+    })
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+        Color::Empty => unreachable!(),
+    }
+}
+
+impl Board {
+    pub fn pocket(&self, color: Color, typ: PieceType) -> u8 {
+        self.pockets[color_index(color)][piece_index(typ)]
+    }
+
+    pub fn add_to_pocket(&mut self, color: Color, typ: PieceType) {
+        let count = &mut self.pockets[color_index(color)][piece_index(typ)];
+        self.zobrist ^= pocket_keys()[color_index(color)][piece_index(typ)][*count as usize];
+        *count += 1;
+    }
+
+    pub fn remove_from_pocket(&mut self, color: Color, typ: PieceType) {
+        let count = &mut self.pockets[color_index(color)][piece_index(typ)];
+        *count -= 1;
+        self.zobrist ^= pocket_keys()[color_index(color)][piece_index(typ)][*count as usize];
+    }
+
+    /// The part of the hash that comes from the pockets.
+    pub fn pocket_zobrist(&self) -> u64 {
+        let mut zobrist = 0;
+        for color in [Color::White, Color::Black] {
+            for typ in POCKET_PIECES {
+                for count in 0..self.pocket(color, typ) {
+                    zobrist ^= pocket_keys()[color_index(color)][piece_index(typ)][count as usize];
+                }
+            }
+        }
+        zobrist
+    }
+
+    /// Drops of the pieces in hand onto the empty squares of `mask`, which
+    /// is the squares between the king and a slider giving check.
+    pub fn generate_drops(&self, mask: Bitmap) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let empty = !(self.white_pieces | self.black_pieces) & mask;
+
+        for typ in POCKET_PIECES {
+            if self.pocket(self.turn, typ) == 0 {
+                continue;
+            }
+            let mut squares = match typ {
+                PieceType::Pawn => empty & PAWN_DROP_SQUARES,
+                _ => empty,
+            };
+            while squares > 0 {
+                moves.push(Move::drop(typ, squares.pop_lsb()));
+            }
+        }
+        moves
+    }
+
+    pub fn make_drop(&mut self, mov: &Move) {
+        self.remove_from_pocket(self.turn, mov.promotion);
+        self.put_piece(
+            mov.end_square,
+            Piece {
+                typ: mov.promotion,
+                color: self.turn,
+            },
+        );
+    }
+
+    pub fn unmake_drop(&mut self, mov: &Move) {
+        self.remove_piece(mov.end_square);
+        self.add_to_pocket(self.turn, mov.promotion);
+    }
+
+    /// Puts what `mov` captures into the pocket of the side moving, a
+    /// promoted piece as the pawn it was, and keeps track of the promoted
+    /// pieces. Called before the move is made.
+    pub fn pocket_captures(&mut self, mov: &Move, captured_piece: Piece) {
+        let en_passant = mov.end_square == self.en_passant_target
+            && self.get_piece(mov.start_square).typ == PieceType::Pawn;
+
+        if captured_piece.color != Color::Empty {
+            let typ = match self.promoted & (1 << mov.end_square) > 0 {
+                true => PieceType::Pawn,
+                false => captured_piece.typ,
+            };
+            self.add_to_pocket(self.turn, typ);
+        } else if en_passant {
+            self.add_to_pocket(self.turn, PieceType::Pawn);
+        }
+
+        let moving_promoted = self.promoted & (1 << mov.start_square) > 0;
+        self.promoted &= !((1 << mov.start_square) | (1 << mov.end_square));
+        if moving_promoted || mov.promotion != PieceType::Empty {
+            self.promoted |= 1 << mov.end_square;
+        }
+    }
+
+    /// Undoes `pocket_captures`, called before the move is taken back with
+    /// the side that made it to move.
+    pub fn unpocket_captures(&mut self, mov: &Move, irreversible: &Irreversible) {
+        let en_passant = mov.end_square == irreversible.en_passant_target
+            && mov.promotion == PieceType::Empty
+            && self.get_piece(mov.end_square).typ == PieceType::Pawn;
+
+        if irreversible.captured_piece.color != Color::Empty {
+            let typ = match irreversible.captured_promoted {
+                true => PieceType::Pawn,
+                false => irreversible.captured_piece.typ,
+            };
+            self.remove_from_pocket(self.turn, typ);
+        } else if en_passant {
+            self.remove_from_pocket(self.turn, PieceType::Pawn);
+        }
+
+        let moved_promoted =
+            mov.promotion == PieceType::Empty && self.promoted & (1 << mov.end_square) > 0;
+        self.promoted &= !(1 << mov.end_square);
+        if moved_promoted {
+            self.promoted |= 1 << mov.start_square;
+        }
+        if irreversible.captured_promoted {
+            self.promoted |= 1 << mov.end_square;
+        }
+    }
+
+    /// The material in hand, from white's point of view.
+    pub fn pocket_material(&self) -> Score {
+        let mut score = Score::default();
+        for typ in POCKET_PIECES {
+            let count =
+                self.pocket(Color::White, typ) as i32 - self.pocket(Color::Black, typ) as i32;
+            score += MATERIAL[piece_index(typ)] * count;
+        }
+        score
+    }
+
+    /// Fills the pockets from the fen notation, e.g. `QPPnb`.
+    pub fn parse_pocket(&mut self, pocket: &str) {
+        for piece in pocket.chars() {
+            let color = match piece.is_uppercase() {
+                true => Color::White,
+                false => Color::Black,
+            };
+            let typ = match piece.to_ascii_lowercase() {
+                'p' => PieceType::Pawn,
+                'n' => PieceType::Knight,
+                'b' => PieceType::Bishop,
+                'r' => PieceType::Rook,
+                'q' => PieceType::Queen,
+                _ => continue,
+            };
+            self.pockets[color_index(color)][piece_index(typ)] += 1;
+        }
+    }
+
+    /// The pockets in fen notation, white's pieces first.
+    pub fn pocket_fen(&self) -> String {
+        let mut pocket = String::new();
+        for color in [Color::White, Color::Black] {
+            for typ in POCKET_PIECES.iter().rev() {
+                let piece = Self::converter(Piece { typ: *typ, color });
+                for _ in 0..self.pocket(color, *typ) {
+                    pocket.push(piece);
+                }
+            }
+        }
+        pocket
+    }
+}
@@ -0,0 +1,380 @@
+use crate::board::{BitOperations, Bitmap, Board, Color, Square, SquareOperations};
+use crate::eval::Score;
+use crate::pawn_structure::FILE_MASKS;
+use crate::psqt::MATERIAL;
+
+use std::sync::OnceLock;
+
+/// A score that is clearly winning but still below any mate.
+pub const KNOWN_WIN: i32 = 5000;
+
+/// Scale factors are out of this, a normal position is not scaled at all.
+pub const NORMAL_SCALE: i32 = 64;
+pub const OPPOSITE_BISHOPS_SCALE: i32 = 16;
+pub const NO_PAWNS_SCALE: i32 = 16;
+
+const LIGHT_SQUARES: Bitmap = 0x55AA55AA55AA55AA;
+
+pub fn distance(a: Square, b: Square) -> i32 {
+    let files = (a.file() as i32 - b.file() as i32).abs();
+    let ranks = (a.rank() as i32 - b.rank() as i32).abs();
+    i32::max(files, ranks)
+}
+
+/// How far a square is from the edge of the board, 0 on the edge.
+fn distance_to_edge(square: Square) -> i32 {
+    let file = square.file() as i32;
+    let rank = square.rank() as i32;
+    i32::min(i32::min(file, 7 - file), i32::min(rank, 7 - rank))
+}
+
+fn other(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        _ => Color::White,
+    }
+}
+
+/// The pieces of one side.
+struct Side {
+    pawns: Bitmap,
+    knights: Bitmap,
+    bishops: Bitmap,
+    rooks: Bitmap,
+    queens: Bitmap,
+    king: Square,
+}
+
+impl Side {
+    fn minors(&self) -> u32 {
+        (self.knights | self.bishops).count_ones()
+    }
+
+    fn pieces(&self) -> u32 {
+        (self.knights | self.bishops | self.rooks | self.queens).count_ones()
+    }
+
+    fn is_bare(&self) -> bool {
+        self.pawns == 0 && self.pieces() == 0
+    }
+
+    /// The value of the pieces that are not pawns, in midgame material.
+    fn non_pawn_material(&self) -> i32 {
+        [self.knights, self.bishops, self.rooks, self.queens]
+            .iter()
+            .zip(&MATERIAL[1..5])
+            .map(|(pieces, value)| pieces.count_ones() as i32 * value.mg)
+            .sum()
+    }
+}
+
+// The KPK bitbase, from white's point of view with the pawn on files a-d.
+const KPK_SIZE: usize = 2 * 24 * 64 * 64;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+fn kpk_index(white_to_move: bool, black_king: Square, white_king: Square, pawn: Square) -> usize {
+    white_king as usize
+        | (black_king as usize) << 6
+        | (!white_to_move as usize) << 12
+        | (pawn.file() as usize) << 13
+        | (6 - pawn.rank() as usize) << 15
+}
+
+fn king_moves(square: Square) -> Bitmap {
+    Board::king_attacks(1 << square) & !(1 << square)
+}
+
+fn kpk_initial(index: usize) -> u8 {
+    let white_king = (index & 0x3F) as Square;
+    let black_king = ((index >> 6) & 0x3F) as Square;
+    let white_to_move = (index >> 12) & 1 == 0;
+    let pawn = (((index >> 13) & 3) + 8 * (6 - ((index >> 15) & 7))) as Square;
+    let pawn_attacks = Board::white_pawn_attacks(1 << pawn);
+    let push = pawn + 8;
+
+    if distance(white_king, black_king) <= 1
+        || white_king == pawn
+        || black_king == pawn
+        || (white_to_move && pawn_attacks & (1 << black_king) > 0)
+    {
+        INVALID
+    } else if white_to_move
+        && pawn.rank() == 6
+        && white_king != push
+        && (distance(black_king, push) > 1 || king_moves(white_king) & (1 << push) > 0)
+    {
+        WIN
+    } else if !white_to_move
+        && (king_moves(black_king) & !(king_moves(white_king) | pawn_attacks) == 0
+            || king_moves(black_king) & (1 << pawn) & !king_moves(white_king) > 0)
+    {
+        DRAW
+    } else {
+        UNKNOWN
+    }
+}
+
+fn kpk_classify(bitbase: &[u8], index: usize) -> u8 {
+    let white_king = (index & 0x3F) as Square;
+    let black_king = ((index >> 6) & 0x3F) as Square;
+    let white_to_move = (index >> 12) & 1 == 0;
+    let pawn = (((index >> 13) & 3) + 8 * (6 - ((index >> 15) & 7))) as Square;
+
+    let (good, bad) = match white_to_move {
+        true => (WIN, DRAW),
+        false => (DRAW, WIN),
+    };
+
+    let mut result = INVALID;
+    if white_to_move {
+        let mut moves = king_moves(white_king);
+        while moves > 0 {
+            result |= bitbase[kpk_index(false, black_king, moves.pop_lsb(), pawn)];
+        }
+        if pawn.rank() < 6 {
+            result |= bitbase[kpk_index(false, black_king, white_king, pawn + 8)];
+        }
+        if pawn.rank() == 1 && pawn + 8 != white_king && pawn + 8 != black_king {
+            result |= bitbase[kpk_index(false, black_king, white_king, pawn + 16)];
+        }
+    } else {
+        let mut moves = king_moves(black_king);
+        while moves > 0 {
+            result |= bitbase[kpk_index(true, moves.pop_lsb(), white_king, pawn)];
+        }
+    }
+
+    if result & good > 0 {
+        good
+    } else if result & UNKNOWN > 0 {
+        UNKNOWN
+    } else {
+        bad
+    }
+}
+
+/// Retrograde analysis of every king and pawn versus king position until
+/// nothing changes, the positions that are still unknown are draws.
+fn generate_kpk() -> Vec<u8> {
+    let mut bitbase = (0..KPK_SIZE).map(kpk_initial).collect::<Vec<_>>();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..KPK_SIZE {
+            if bitbase[index] == UNKNOWN {
+                let result = kpk_classify(&bitbase, index);
+                if result != UNKNOWN {
+                    bitbase[index] = result;
+                    changed = true;
+                }
+            }
+        }
+    }
+    bitbase
+}
+
+static KPK: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Whether the side with the pawn wins, with the squares given from the
+/// point of view of the side with the pawn.
+pub fn kpk_probe(
+    strong_king: Square,
+    pawn: Square,
+    weak_king: Square,
+    strong_to_move: bool,
+) -> bool {
+    // Mirror the pawn onto files a-d.
+    let flip = if pawn.file() >= 4 { 7 } else { 0 };
+    let bitbase = KPK.get_or_init(generate_kpk);
+    bitbase[kpk_index(
+        strong_to_move,
+        weak_king ^ flip,
+        strong_king ^ flip,
+        pawn ^ flip,
+    )] == WIN
+}
+
+impl Board {
+    fn side(&self, color: Color) -> Side {
+        let own = match color {
+            Color::White => self.white_pieces,
+            _ => self.black_pieces,
+        };
+        Side {
+            pawns: self.pawns & own,
+            knights: self.knights & own,
+            bishops: self.bishops & own,
+            rooks: self.rooks & own,
+            queens: self.queens & own,
+            king: (self.kings & own).lsb(),
+        }
+    }
+
+    /// Neither side can possibly mate, e.g. KvK, KBvK, KNvK, KNNvK or only
+    /// bishops on squares of the same colour.
+    pub fn insufficient_material(&self) -> bool {
+        if self.pawns | self.rooks | self.queens > 0 {
+            return false;
+        }
+
+        let minors = self.knights | self.bishops;
+        let white = (minors & self.white_pieces).count_ones();
+        let black = (minors & self.black_pieces).count_ones();
+        if white + black <= 1 {
+            return true;
+        }
+        if self.knights == 0
+            && (self.bishops & LIGHT_SQUARES == 0 || self.bishops & !LIGHT_SQUARES == 0)
+        {
+            return true;
+        }
+
+        // Two knights can not force mate against a bare king.
+        (white == 0 || black == 0) && self.bishops == 0 && self.knights.count_ones() == 2
+    }
+
+    fn kpk(&self, strong: Color) -> i32 {
+        let side = self.side(strong);
+        let weak_king = self.side(other(strong)).king;
+
+        // Look at the board from the strong side.
+        let flip = if strong == Color::White { 0 } else { 56 };
+        let pawn = side.pawns.lsb() ^ flip;
+        if !kpk_probe(
+            side.king ^ flip,
+            pawn,
+            weak_king ^ flip,
+            self.turn == strong,
+        ) {
+            return 0;
+        }
+        KNOWN_WIN + MATERIAL[0].eg + pawn.rank() as i32 * 10
+    }
+
+    /// Drives the weak king into a corner of the bishop's colour.
+    fn kbnk(&self, strong: Color) -> i32 {
+        let side = self.side(strong);
+        let weak_king = self.side(other(strong)).king;
+
+        let corners: [Square; 2] = match side.bishops & LIGHT_SQUARES > 0 {
+            true => [7, 56],
+            false => [0, 63],
+        };
+        let corner_distance = corners
+            .iter()
+            .map(|corner| distance(weak_king, *corner))
+            .min()
+            .unwrap();
+
+        KNOWN_WIN + 20 * (7 - distance(side.king, weak_king)) + 40 * (7 - corner_distance)
+    }
+
+    /// Drives the bare king to the edge with the strong king close by, for
+    /// KRK, KQK and anything with more material.
+    fn mop_up(&self, strong: Color) -> i32 {
+        let side = self.side(strong);
+        let weak_king = self.side(other(strong)).king;
+
+        KNOWN_WIN
+            + side.non_pawn_material()
+            + 40 * (3 - distance_to_edge(weak_king))
+            + 20 * (7 - distance(side.king, weak_king))
+    }
+
+    /// A king with bishop and rook pawns that can not win because the bishop
+    /// does not control the promotion square the defending king guards.
+    fn wrong_bishop(&self, strong: Color) -> bool {
+        let side = self.side(strong);
+        let weak_king = self.side(other(strong)).king;
+
+        if side.pawns == 0 || side.knights | side.rooks | side.queens > 0 || side.minors() != 1 {
+            return false;
+        }
+
+        [0, 7].iter().any(|file| {
+            let promotion = match strong {
+                Color::White => 56 + file,
+                _ => *file,
+            };
+            side.pawns & !FILE_MASKS[*file as usize] == 0
+                && (side.bishops & LIGHT_SQUARES > 0) != (LIGHT_SQUARES & (1 << promotion) > 0)
+                && distance(weak_king, promotion) <= 1
+        })
+    }
+
+    /// The score of positions with a specialised evaluator from white's
+    /// point of view, or `None` when the normal evaluation applies.
+    pub fn endgame_eval(&self) -> Option<i32> {
+        if self.insufficient_material() {
+            return Some(0);
+        }
+
+        for strong in [Color::White, Color::Black] {
+            let sign = strong as i32 / 8;
+            let side = self.side(strong);
+            if !self.side(other(strong)).is_bare() {
+                continue;
+            }
+
+            if self.wrong_bishop(strong) {
+                return Some(0);
+            }
+            if side.pawns.count_ones() == 1 && side.pieces() == 0 {
+                return Some(sign * self.kpk(strong));
+            }
+            if side.pawns == 0
+                && side.knights.count_ones() == 1
+                && side.bishops.count_ones() == 1
+                && side.pieces() == 2
+            {
+                return Some(sign * self.kbnk(strong));
+            }
+            if side.rooks | side.queens > 0 {
+                return Some(sign * self.mop_up(strong));
+            }
+        }
+        None
+    }
+
+    /// How much of the endgame score the stronger side can hope to convert,
+    /// out of `NORMAL_SCALE`.
+    pub fn scale_factor(&self, score: Score) -> i32 {
+        let strong = if score.eg >= 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let side = self.side(strong);
+        let weak = self.side(other(strong));
+
+        let only_bishops = side.knights | side.rooks | side.queens == 0
+            && weak.knights | weak.rooks | weak.queens == 0;
+        if only_bishops
+            && side.bishops.count_ones() == 1
+            && weak.bishops.count_ones() == 1
+            && ((side.bishops | weak.bishops) & LIGHT_SQUARES).count_ones() == 1
+        {
+            return OPPOSITE_BISHOPS_SCALE;
+        }
+
+        // Without pawns a minor piece more is rarely enough to win.
+        if side.pawns == 0 && side.non_pawn_material() - weak.non_pawn_material() <= MATERIAL[2].mg
+        {
+            return NO_PAWNS_SCALE;
+        }
+
+        NORMAL_SCALE
+    }
+
+    /// Blends midgame and endgame with the endgame scaled down in drawish
+    /// endings.
+    pub fn taper_scaled(&self, score: Score) -> i32 {
+        let eg = score.eg * self.scale_factor(score) / NORMAL_SCALE;
+        Score::new(score.mg, eg).taper(self.phase)
+    }
+}
@@ -0,0 +1,317 @@
+use crate::board::Board;
+use crate::options::Options;
+use crate::r#move::Move;
+use crate::search::{Protocol, SearchLimits, TranspositionTable};
+
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// How long every position is searched when neither a depth nor a
+/// movetime is given.
+pub const EPD_MOVETIME: u64 = 1000;
+
+/// A position of a test suite with the opcodes the runner knows.
+#[derive(Debug, Clone, Default)]
+pub struct EpdPosition {
+    pub fen: String,
+    /// The moves of `bm`, any of which solves the position.
+    pub best_moves: Vec<String>,
+    /// The moves of `am`, none of which may be played.
+    pub avoid_moves: Vec<String>,
+    pub id: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl EpdPosition {
+    /// Reads one line of an EPD file: the first four fields of a fen
+    /// followed by opcodes ending in `;`, e.g.
+    /// `... w - - bm Qg6; id "WAC.001";`.
+    pub fn parse(line: &str) -> Option<Self> {
+        // The fields may be separated by any run of spaces or tabs, and the
+        // rest of the line holds the opcodes.
+        let mut rest = line.trim();
+        let mut fen = Vec::new();
+        for _ in 0..4 {
+            let (field, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if field.is_empty() {
+                return None;
+            }
+            fen.push(field);
+            rest = tail.trim_start();
+        }
+
+        let mut position = EpdPosition {
+            fen: fen.join(" ") + " 0 1",
+            ..Default::default()
+        };
+
+        for operation in split_operations(rest) {
+            let (opcode, operands) = operation
+                .split_once(char::is_whitespace)
+                .unwrap_or((&operation, ""));
+            let operands = operands.trim();
+            match opcode {
+                "bm" => {
+                    position.best_moves = operands.split_whitespace().map(String::from).collect()
+                }
+                "am" => {
+                    position.avoid_moves = operands.split_whitespace().map(String::from).collect()
+                }
+                "id" => position.id = Some(operands.trim_matches('"').to_string()),
+                "c0" => position.comment = Some(operands.trim_matches('"').to_string()),
+                _ => (),
+            }
+        }
+        Some(position)
+    }
+
+    /// The points every move of the `c0` comment is worth, as the Strategic
+    /// Test Suite gives them, e.g. `"f5=10, Be5+=2, Bf2=3"`.
+    fn points(&self) -> Vec<(String, u32)> {
+        let comment = match &self.comment {
+            Some(comment) => comment,
+            None => return Vec::new(),
+        };
+        comment
+            .split(',')
+            .filter_map(|entry| {
+                let (mov, points) = entry.trim().split_once('=')?;
+                Some((mov.to_string(), points.trim().parse().ok()?))
+            })
+            .collect()
+    }
+}
+
+/// Splits the opcodes at the `;` that are not inside a quoted string.
+fn split_operations(operations: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for chr in operations.chars() {
+        match chr {
+            '"' => {
+                quoted = !quoted;
+                current.push(chr);
+            }
+            ';' if !quoted => {
+                if !current.trim().is_empty() {
+                    result.push(current.trim().to_string());
+                }
+                current.clear();
+            }
+            _ => current.push(chr),
+        }
+    }
+    if !current.trim().is_empty() {
+        result.push(current.trim().to_string());
+    }
+    result
+}
+
+/// What came of searching one position.
+struct EpdResult {
+    solved: bool,
+    points: u32,
+    max_points: u32,
+}
+
+/// Resolves the moves of an opcode, which are written in SAN.
+fn resolve(board: &mut Board, moves: &[String]) -> Result<Vec<Move>, String> {
+    moves
+        .iter()
+        .map(|mov| {
+            board
+                .parse_san(mov)
+                .ok_or_else(|| format!("\"{mov}\" is not a legal move"))
+        })
+        .collect()
+}
+
+fn run_position(
+    position: &EpdPosition,
+    limits: &SearchLimits,
+) -> Result<(Move, EpdResult), String> {
+    let mut board = Board::try_from_fen(&position.fen)?;
+    if !board.has_kings() {
+        return Err("the position needs a king of each color".to_string());
+    }
+    let best_moves = resolve(&mut board, &position.best_moves)?;
+    let avoid_moves = resolve(&mut board, &position.avoid_moves)?;
+
+    let options = Options::new();
+    let transposition_table = Arc::new(RwLock::new(TranspositionTable::new(
+        options.spin("Hash") as usize
+    )));
+    let result = board.search(
+        limits,
+        &Arc::new(AtomicBool::new(false)),
+        &options,
+        &transposition_table,
+        &Arc::new(AtomicBool::new(false)),
+        &Arc::new(AtomicUsize::new(0)),
+    );
+    let mov = match result.first() {
+        Some(best) if best.mov != Move::null() => best.mov,
+        _ => return Err("the position has no legal moves".to_string()),
+    };
+
+    let solved =
+        (best_moves.is_empty() || best_moves.contains(&mov)) && !avoid_moves.contains(&mov);
+
+    let points = position.points();
+    let (points, max_points) = match points.iter().map(|(_, points)| *points).max() {
+        Some(max_points) => {
+            let played = points
+                .iter()
+                .find(|(san, _)| board.parse_san(san) == Some(mov))
+                .map_or(0, |(_, points)| *points);
+            (played, max_points)
+        }
+        None => (solved as u32, 1),
+    };
+
+    Ok((
+        mov,
+        EpdResult {
+            solved,
+            points,
+            max_points,
+        },
+    ))
+}
+
+/// Searches every position of the EPD file at `path` with `limits` on
+/// `jobs` threads at a time, printing how each went and the total score.
+/// Returns whether the file could be read.
+pub fn run_epd(path: &str, limits: SearchLimits, jobs: usize) -> bool {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            println!("Could not open \"{path}\": {error}");
+            return false;
+        }
+    };
+    let positions = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let position = EpdPosition::parse(line);
+            if position.is_none() {
+                println!("skipping \"{line}\", it is not a valid epd line");
+            }
+            position
+        })
+        .collect::<Vec<_>>();
+
+    // Without a `bm` or an `am` any move would solve the position.
+    let (positions, unscored): (Vec<_>, Vec<_>) = positions
+        .into_iter()
+        .partition(|position| !position.best_moves.is_empty() || !position.avoid_moves.is_empty());
+    for position in &unscored {
+        let id = position.id.as_deref().unwrap_or(&position.fen);
+        println!("skipping {id}, it has no bm or am");
+    }
+
+    let limits = SearchLimits {
+        protocol: Protocol::Silent,
+        ..limits
+    };
+    let pool = ThreadPoolBuilder::new().num_threads(jobs).build().unwrap();
+    let done = AtomicUsize::new(0);
+
+    let results = pool.install(|| {
+        positions
+            .par_iter()
+            .map(|position| {
+                let result = run_position(position, &limits);
+                let count = done.fetch_add(1, Ordering::SeqCst) + 1;
+                let id = position.id.as_deref().unwrap_or(&position.fen);
+                let line = match &result {
+                    Ok((mov, result)) => {
+                        let mut board = Board::from_fen(position.fen.clone());
+                        format!(
+                            "{:6} {id}: played {}, bm {}{}",
+                            if result.solved { "ok" } else { "FAILED" },
+                            board.move_to_san(mov),
+                            position.best_moves.join(" "),
+                            match position.avoid_moves.is_empty() {
+                                true => String::new(),
+                                false => format!(", am {}", position.avoid_moves.join(" ")),
+                            },
+                        )
+                    }
+                    Err(error) => format!("{:6} {id}: {error}", "ERROR"),
+                };
+                println!("[{count}/{}] {line}", positions.len());
+                result.ok().map(|(_, result)| result)
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let results = results.into_iter().flatten().collect::<Vec<_>>();
+    let solved = results.iter().filter(|result| result.solved).count();
+    let points = results.iter().map(|result| result.points).sum::<u32>();
+    let max_points = results.iter().map(|result| result.max_points).sum::<u32>();
+
+    println!();
+    println!(
+        "Solved {solved}/{} positions, {} could not be run, {} have no bm or am",
+        results.len(),
+        positions.len() - results.len(),
+        unscored.len()
+    );
+    println!(
+        "Score {points}/{max_points} ({:.1}%)",
+        points as f64 * 100.0 / u32::max(max_points, 1) as f64
+    );
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fields_separated_by_any_whitespace() {
+        let position = EpdPosition::parse(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1  w\t-  -\tbm Qg6;  id \"WAC.001\";",
+        )
+        .unwrap();
+        assert_eq!(
+            position.fen,
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(position.best_moves, ["Qg6"]);
+        assert_eq!(position.id.as_deref(), Some("WAC.001"));
+    }
+
+    #[test]
+    fn parses_several_moves_and_comments() {
+        let position = EpdPosition::parse(
+            "8/8/8/8/8/8/8/K6k w - - bm\tKb1 Kb2; am Ka2; c0 \"Kb1=10, Kb2=5\";",
+        )
+        .unwrap();
+        assert_eq!(position.best_moves, ["Kb1", "Kb2"]);
+        assert_eq!(position.avoid_moves, ["Ka2"]);
+        assert_eq!(
+            position.points(),
+            [("Kb1".to_string(), 10), ("Kb2".to_string(), 5)]
+        );
+    }
+
+    #[test]
+    fn fails_positions_without_both_kings() {
+        let position = EpdPosition::parse("8/8/8/8/8/8/8/7k w - - bm Kg1;").unwrap();
+        let limits = SearchLimits {
+            depth: Some(1),
+            protocol: Protocol::Silent,
+            ..Default::default()
+        };
+        assert!(run_position(&position, &limits).is_err());
+    }
+}
@@ -1,10 +1,77 @@
 use crate::board::{Board, Color};
+use crate::endgame::NORMAL_SCALE;
+use crate::params::{Params, PARAMS};
 use crate::piece::PieceType;
+use crate::variant::Variant;
 
 use std::cmp::Ordering;
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
+pub const MAX_PHASE: i32 = 24;
+
+/// A pair of midgame and endgame values that are blended by the game phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Score { mg, eg }
+    }
+
+    pub fn taper(&self, phase: i32) -> i32 {
+        let phase = i32::min(phase, MAX_PHASE);
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, other: Score) -> Score {
+        Score::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+
+    fn mul(self, factor: i32) -> Score {
+        Score::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        *self = *self - other;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Eval {
     pub score: i64,
     pub mate: Option<u16>,
@@ -20,6 +87,39 @@ impl Eval {
         score: i64::MIN + 101,
         mate: None,
     };
+
+    pub fn as_uci(&self) -> String {
+        match self.mate {
+            Some(mate) if mate % 2 == 1 => format!("mate {}", mate.div_ceil(2)),
+            Some(mate) => format!("mate -{}", mate / 2),
+            None => format!("cp {}", self.score),
+        }
+    }
+
+    /// The score in centipawns, with mates as 100000 plus the moves left
+    /// as xboard expects them.
+    pub fn as_xboard(&self) -> i64 {
+        match self.mate {
+            Some(mate) if mate % 2 == 1 => 100_000 + mate.div_ceil(2) as i64,
+            Some(mate) => -100_000 - (mate / 2) as i64,
+            None => self.score,
+        }
+    }
+
+    /// Win/draw/loss expectation in permille for the side to move, using a
+    /// logistic model where a one pawn advantage is a 50% winning chance.
+    pub fn wdl(&self) -> (u16, u16, u16) {
+        match self.mate {
+            Some(mate) if mate % 2 == 1 => (1000, 0, 0),
+            Some(_) => (0, 0, 1000),
+            None => {
+                let win_rate = |score: f64| 1000.0 / (1.0 + ((100.0 - score) / 60.0).exp());
+                let win = win_rate(self.score as f64).round() as u16;
+                let loss = win_rate(-self.score as f64).round() as u16;
+                (win, 1000 - win - loss, loss)
+            }
+        }
+    }
 }
 
 impl From<i64> for Eval {
@@ -119,20 +219,38 @@ impl Div for Eval {
     }
 }
 
+// A derived `PartialOrd` would compare the scores before the mates, so
+// `<` and `>` have to go through `cmp` as well.
+impl PartialOrd for Eval {
+    fn partial_cmp(&self, other: &Eval) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Ord for Eval {
     fn cmp(&self, other: &Eval) -> Ordering {
+        // The bounds of a full window lie beyond every mate.
+        match (*self, *other) {
+            (a, b) if a == b => return Ordering::Equal,
+            (Eval::MAX, _) | (_, Eval::MIN) => return Ordering::Greater,
+            (Eval::MIN, _) | (_, Eval::MAX) => return Ordering::Less,
+            _ => (),
+        }
+
         // 0 = Opponent has mate
         // 1 = You have mate
+        // The mate is counted in plies, so a quick mate beats a slow one
+        // and a slow loss beats a quick one.
         match (self.mate, other.mate) {
             (Some(self_mate), Some(other_mate)) => {
                 let self_turn = self_mate % 2;
                 let other_turn = other_mate % 2;
 
                 match (self_turn, other_turn) {
-                    (0, 0) => return other_mate.cmp(&self_mate),
+                    (0, 0) => return self_mate.cmp(&other_mate),
                     (0, 1) => return Ordering::Less,
                     (1, 0) => return Ordering::Greater,
-                    (1, 1) => return self_mate.cmp(&other_mate),
+                    (1, 1) => return other_mate.cmp(&self_mate),
                     _ => unreachable!(),
                 }
             }
@@ -155,19 +273,18 @@ impl Ord for Eval {
 
 impl Board {
     pub fn eval(&mut self, self_moves: usize) -> Eval {
-        let white_pawns = self.white_pieces & self.pawns;
-        let white_knights = self.white_pieces & self.knights;
-        let white_bishops = self.white_pieces & self.bishops;
-        let white_rooks = self.white_pieces & self.rooks;
-        let white_queens = self.white_pieces & self.queens;
-
-        let black_pawns = self.black_pieces & self.pawns;
-        let black_knights = self.black_pieces & self.knights;
-        let black_bishops = self.black_pieces & self.bishops;
-        let black_rooks = self.black_pieces & self.rooks;
-        let black_queens = self.black_pieces & self.queens;
-
         if self_moves == 0 {
+            if let Some(result) = self.variant_result() {
+                return result;
+            }
+            // Running out of moves wins antichess.
+            if self.variant == Variant::Antichess {
+                return Eval {
+                    score: 0,
+                    mate: Some(1),
+                };
+            }
+
             if self.is_check() {
                 return Eval {
                     score: 0,
@@ -178,27 +295,104 @@ impl Board {
             }
         }
 
-        let en_passant_target = self.en_passant_target;
-        self.en_passant_target = -1;
-
-        self.change_turn();
-        let other_moves = self.generate_moves().len();
-        self.change_turn();
-
-        self.en_passant_target = en_passant_target;
-
-        (Eval::from(PieceType::Queen)
-            * (Eval::from(white_queens.count_ones()) - Eval::from(black_queens.count_ones()))
-            + Eval::from(PieceType::Rook)
-                * (Eval::from(white_rooks.count_ones()) - Eval::from(black_rooks.count_ones()))
-            + Eval::from(PieceType::Bishop)
-                * (Eval::from(white_bishops.count_ones()) - Eval::from(black_bishops.count_ones()))
-            + Eval::from(PieceType::Knight)
-                * (Eval::from(white_knights.count_ones()) - Eval::from(black_knights.count_ones()))
-            + Eval::from(PieceType::Pawn)
-                * (Eval::from(white_pawns.count_ones()) - Eval::from(black_pawns.count_ones())))
-            * Eval::from(self.turn)
-            / Eval::from(8i64)
-            + Eval::from(10i64) * (Eval::from(self_moves) - Eval::from(other_moves))
+        if self.variant == Variant::Antichess {
+            let score = self.antichess_score().taper(self.phase);
+            return Eval::from(score as i64) * Eval::from(self.turn) / Eval::from(8i64);
+        }
+
+        // The endgame rules and the network only know standard chess.
+        if self.variant == Variant::Standard {
+            if let Some(score) = self.endgame_eval() {
+                return Eval::from(score as i64) * Eval::from(self.turn) / Eval::from(8i64);
+            }
+
+            if let Some(score) = self.nnue_eval() {
+                return Eval::from(score as i64);
+            }
+        }
+
+        let score = self.psqt
+            + self.pawn_structure()
+            + self.king_safety()
+            + self.mobility()
+            + self.piece_activity()
+            + self.pocket_material()
+            + self.variant_score();
+
+        Eval::from(self.taper_scaled(score) as i64) * Eval::from(self.turn) / Eval::from(8i64)
+    }
+}
+
+/// A term of the evaluation with its value for white and for black.
+pub type TraceTerm = (&'static str, Score, Score);
+
+type Term = fn(&Board, Color, &Params) -> Score;
+
+impl Board {
+    /// Every term that goes into `eval` with the given parameters, each for
+    /// white and black.
+    pub fn eval_terms(&self, params: &Params) -> Vec<TraceTerm> {
+        let terms: [(&'static str, Term); 7] = [
+            ("Material", Board::material_for),
+            ("Placement", |board, color, _| board.placement_for(color)),
+            ("Pawns", Board::pawn_structure_for),
+            ("Passed pawns", Board::passed_pawns_for),
+            ("King safety", Board::king_safety_for),
+            ("Mobility", Board::mobility_for),
+            ("Piece activity", Board::piece_activity_for),
+        ];
+
+        terms
+            .iter()
+            .map(|(name, term)| {
+                (
+                    *name,
+                    term(self, Color::White, params),
+                    term(self, Color::Black, params),
+                )
+            })
+            .collect()
+    }
+
+    /// A table of the evaluation terms in midgame and endgame, followed by
+    /// the tapered score from white's point of view.
+    pub fn eval_trace(&self) -> String {
+        let row = |name: &str, white: Score, black: Score| {
+            let total = white - black;
+            format!(
+                "{name:>14} | {:>5} {:>5} | {:>5} {:>5} | {:>5} {:>5}\n",
+                white.mg, white.eg, black.mg, black.eg, total.mg, total.eg
+            )
+        };
+        let separator = "---------------+-------------+-------------+-------------\n";
+
+        let mut trace = String::new();
+        trace += "          Term |    White    |    Black    |    Total\n";
+        trace += "               |   MG    EG  |   MG    EG  |   MG    EG\n";
+        trace += separator;
+
+        let mut white = Score::default();
+        let mut black = Score::default();
+        for (name, white_term, black_term) in self.eval_terms(&PARAMS) {
+            trace += &row(name, white_term, black_term);
+            white += white_term;
+            black += black_term;
+        }
+
+        trace += separator;
+        trace += &row("Total", white, black);
+        trace += &format!("\nPhase: {}/{MAX_PHASE}\n", i32::min(self.phase, MAX_PHASE));
+        trace += &format!(
+            "Scale factor: {}/{NORMAL_SCALE}\n",
+            self.scale_factor(white - black)
+        );
+        trace += &format!(
+            "Final evaluation: {} cp (white side)\n",
+            self.taper_scaled(white - black)
+        );
+        if let Some(score) = self.endgame_eval() {
+            trace += &format!("Endgame evaluation: {score} cp (white side)\n");
+        }
+        trace
     }
 }
@@ -3,6 +3,7 @@ use const_for::const_for;
 use crate::board::*;
 use crate::piece::*;
 use crate::r#move::*;
+use crate::variant::Variant;
 
 const NOT_AFILE: Bitmap = 0x7f7f7f7f7f7f7f7f;
 const NOT_HFILE: Bitmap = 0xfefefefefefefefe;
@@ -31,6 +32,12 @@ const fn generate_king_attack_bitboards() -> [Bitmap; 64] {
     result
 }
 
+/// The squares from `a` to `b` on one rank, both included.
+const fn rank_span(a: Square, b: Square) -> Bitmap {
+    let (low, high) = if a < b { (a, b) } else { (b, a) };
+    (u64::MAX >> (63 - high)) & (u64::MAX << low)
+}
+
 const KING_ATTACK_BITBOARDS: [Bitmap; 64] = generate_king_attack_bitboards();
 
 const fn king_attacks(kings: Bitmap) -> Bitmap {
@@ -60,42 +67,67 @@ impl Board {
             moves.push(Move::new(start_square, end_square, PieceType::Empty));
         }
 
+        moves.append(&mut self.generate_castling_moves(king, enemy_attacks));
+        moves
+    }
+
+    /// Castling with the rights left, `enemy_attacks` being the squares the
+    /// king may not stand on or pass through.
+    pub fn generate_castling_moves(&self, king: Bitmap, enemy_attacks: Bitmap) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let start_square: Square = king.lsb();
         if enemy_attacks & king > 0 {
             return moves;
         }
 
-        match self.turn {
-            Color::White => {
-                if self.castling_rights.white_queen
-                    && (self.white_pieces | self.black_pieces) & 0x0E == 0
-                    && enemy_attacks & 0x1C == 0
-                {
-                    moves.push(Move::new(4, 2, PieceType::Empty))
-                }
-                if self.castling_rights.white_king
-                    && (self.white_pieces | self.black_pieces | enemy_attacks) & !king & 0x70 == 0
-                {
-                    moves.push(Move::new(4, 6, PieceType::Empty))
-                }
-            }
-            Color::Black => {
-                if self.castling_rights.black_queen
-                    && (self.white_pieces | self.black_pieces) & 0x0E00000000000000 == 0
-                    && enemy_attacks & 0x1C00000000000000 == 0
-                {
-                    moves.push(Move::new(60, 58, PieceType::Empty))
-                }
-                if self.castling_rights.black_king
-                    && (self.white_pieces | self.black_pieces | enemy_attacks)
-                        & !king
-                        & 0x7000000000000000
-                        == 0
-                {
-                    moves.push(Move::new(60, 62, PieceType::Empty))
-                }
-            }
+        // Every square between the king, the rook and where they go has to
+        // be empty, and the king may not pass through check. The rook can
+        // also be hiding an attack along the back rank on the king's target.
+        // In atomic chess the target is only checked once the castle is
+        // made, as the rook on its new square may block the attack or the
+        // king may land next to the enemy king.
+        let occupied = self.white_pieces | self.black_pieces;
+        let (rank, rights) = match self.turn {
+            Color::White => (
+                0,
+                [
+                    self.castling_rights.white_king,
+                    self.castling_rights.white_queen,
+                ],
+            ),
+            Color::Black => (
+                56,
+                [
+                    self.castling_rights.black_king,
+                    self.castling_rights.black_queen,
+                ],
+            ),
             Color::Empty => unreachable!(),
         };
+        for file in rights.into_iter().flatten() {
+            let mov = Move::new(start_square, rank + file, PieceType::Empty);
+            let (king_target, rook_target) = Self::castling_squares(&mov);
+            let castling_pieces = king | (1 << mov.end_square);
+            let king_path = rank_span(start_square, king_target);
+            let rook_path = rank_span(mov.end_square, rook_target);
+            let enemy_sliders = self.enemy_pieces() & (self.rooks | self.queens);
+
+            let target_is_safe = if self.variant == Variant::Atomic {
+                true
+            } else {
+                1 << king_target & enemy_attacks == 0
+                    && Self::rook_attacks(1 << king_target, occupied & !castling_pieces, 0)
+                        & enemy_sliders
+                        == 0
+            };
+
+            if (king_path | rook_path) & occupied & !castling_pieces == 0
+                && king_path & !(1 << king_target) & enemy_attacks == 0
+                && target_is_safe
+            {
+                moves.push(mov);
+            }
+        }
 
         moves
     }
@@ -0,0 +1,139 @@
+use crate::board::{BitOperations, Bitmap, Board, Color, SquareOperations};
+use crate::eval::Score;
+use crate::params::{Params, PARAMS};
+use crate::pawn_structure::{FILE_MASKS, RANK_MASKS};
+
+// How much each piece adds for every square of the king zone it attacks.
+pub const KNIGHT_ATTACK_UNITS: i32 = 2;
+pub const BISHOP_ATTACK_UNITS: i32 = 2;
+pub const ROOK_ATTACK_UNITS: i32 = 3;
+pub const QUEEN_ATTACK_UNITS: i32 = 5;
+
+const MAX_ATTACK_UNITS: usize = 100;
+
+const fn generate_attack_table() -> [i32; MAX_ATTACK_UNITS] {
+    let mut result = [0; MAX_ATTACK_UNITS];
+    let mut units = 0;
+    while units < MAX_ATTACK_UNITS {
+        let value = (units * units) as i32 * 2 / 3;
+        result[units] = if value > 500 { 500 } else { value };
+        units += 1;
+    }
+    result
+}
+
+/// The penalty for the attack units against a king. It grows quadratically
+/// so that several attackers count for a lot more than a single one.
+pub const KING_ATTACK: [i32; MAX_ATTACK_UNITS] = generate_attack_table();
+
+impl Board {
+    fn pawn_shelter(&self, color: Color, params: &Params) -> Score {
+        let (own, enemy) = match color {
+            Color::White => (self.white_pieces, self.black_pieces),
+            _ => (self.black_pieces, self.white_pieces),
+        };
+        let own_pawns = self.pawns & own;
+        let enemy_pawns = self.pawns & enemy;
+
+        let king_square = (self.kings & own).lsb();
+        let king_file = king_square.file() as usize;
+        let king_rank = king_square.rank() as i32;
+        let direction = color as i32 / 8;
+
+        let mut score = Score::default();
+        for file in &FILE_MASKS[king_file.saturating_sub(1)..=usize::min(king_file + 1, 7)] {
+            let shield = (1..=2)
+                .map(|distance| king_rank + direction * distance)
+                .position(|rank| {
+                    (0..8).contains(&rank) && own_pawns & file & RANK_MASKS[rank as usize] > 0
+                });
+            score += match shield {
+                Some(distance) => params.pawn_shield[distance],
+                None => params.missing_shield_pawn,
+            };
+
+            let storm = (1..=4)
+                .map(|distance| king_rank + direction * distance)
+                .position(|rank| {
+                    (0..8).contains(&rank) && enemy_pawns & file & RANK_MASKS[rank as usize] > 0
+                });
+            if let Some(distance) = storm {
+                score += params.pawn_storm[distance + 1];
+            }
+
+            if own_pawns & file == 0 {
+                score += if enemy_pawns & file == 0 {
+                    params.open_file_near_king
+                } else {
+                    params.half_open_file_near_king
+                };
+            }
+        }
+        score
+    }
+
+    fn king_attackers(&self, color: Color) -> Score {
+        let (own, enemy) = match color {
+            Color::White => (self.white_pieces, self.black_pieces),
+            _ => (self.black_pieces, self.white_pieces),
+        };
+        let occupied = self.white_pieces | self.black_pieces;
+        let king_zone = Self::king_attacks(self.kings & own);
+
+        let mut attackers = 0;
+        let mut units = 0;
+        let mut count = |attacks: Bitmap, weight: i32| {
+            let attacked = (attacks & king_zone).count_ones() as i32;
+            if attacked > 0 {
+                attackers += 1;
+                units += attacked * weight;
+            }
+        };
+
+        let mut knights = self.knights & enemy;
+        while knights > 0 {
+            let square = knights.pop_lsb();
+            count(Self::knight_attacks(1 << square), KNIGHT_ATTACK_UNITS);
+        }
+        let mut bishops = self.bishops & enemy;
+        while bishops > 0 {
+            let square = bishops.pop_lsb();
+            count(
+                Self::bishop_attacks(1 << square, occupied, 0),
+                BISHOP_ATTACK_UNITS,
+            );
+        }
+        let mut rooks = self.rooks & enemy;
+        while rooks > 0 {
+            let square = rooks.pop_lsb();
+            count(
+                Self::rook_attacks(1 << square, occupied, 0),
+                ROOK_ATTACK_UNITS,
+            );
+        }
+        let mut queens = self.queens & enemy;
+        while queens > 0 {
+            let square = queens.pop_lsb();
+            count(
+                Self::queen_attacks(1 << square, occupied, 0),
+                QUEEN_ATTACK_UNITS,
+            );
+        }
+
+        // A lone attacker is rarely dangerous.
+        if attackers < 2 {
+            return Score::default();
+        }
+        let penalty = KING_ATTACK[usize::min(units as usize, MAX_ATTACK_UNITS - 1)];
+        Score::new(-penalty, -penalty / 4)
+    }
+
+    pub fn king_safety_for(&self, color: Color, params: &Params) -> Score {
+        self.pawn_shelter(color, params) + self.king_attackers(color)
+    }
+
+    /// The safety of both kings from white's point of view.
+    pub fn king_safety(&self) -> Score {
+        self.king_safety_for(Color::White, &PARAMS) - self.king_safety_for(Color::Black, &PARAMS)
+    }
+}
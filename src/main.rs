@@ -1,6 +1,8 @@
 mod board;
 mod r#move;
 mod piece;
+mod psqt;
+mod variant;
 
 mod king_move_generation;
 mod knight_move_generation;
@@ -8,26 +10,45 @@ mod move_generation;
 mod pawn_move_generation;
 mod sliding_pieces_move_generation;
 
+mod antichess;
+mod atomic;
 mod check;
+mod crazyhouse;
 mod enemy_attacks;
 
+mod options;
 mod uci;
+mod xboard;
 
+mod bench;
+mod cli;
+mod endgame;
 mod eval;
+mod nnue;
+mod params;
+mod king_safety;
+mod pawn_structure;
+mod piece_activity;
 mod perft;
 mod search;
+mod search_controller;
+mod syzygy;
 
+mod epd;
 mod pgn_to_fen;
+mod san;
+mod polyglot;
+mod selfplay;
+mod server;
+mod sprt;
+mod tune;
 
-use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::process::ExitCode;
+use std::sync::{Arc, RwLock};
 
 use rayon::ThreadPoolBuilder;
 
-fn main() {
-    let stopper = Arc::new(AtomicBool::new(true));
-    let mut board = board::Board::new();
-
+fn main() -> ExitCode {
     if false {
         ThreadPoolBuilder::new()
             .num_threads(1)
@@ -35,10 +56,65 @@ fn main() {
             .unwrap();
     }
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        None | Some("uci") => {
+            protocol_loop();
+            ExitCode::SUCCESS
+        }
+        Some(_) => cli::run(&args),
+    }
+}
+
+/// Reads UCI commands from stdin until it closes, or xboard commands if
+/// that is what the first command asks for.
+fn protocol_loop() {
+    let mut controller = search_controller::SearchController::new();
+    let mut board = board::Board::new();
+    let mut options = options::Options::new();
+    let transposition_table = Arc::new(RwLock::new(search::TranspositionTable::new(
+        options.spin("Hash") as usize,
+    )));
+
+    let mut debug = false;
+    let mut xboard = None;
+    let mut first_command = true;
+
     loop {
         let mut line = String::new();
-        std::io::stdin().read_line(&mut line).unwrap();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
 
-        board = uci::handle_input(line, board, &stopper);
+        // The first command tells which protocol the GUI speaks.
+        if first_command && !line.trim().is_empty() {
+            first_command = false;
+            if line.split_whitespace().next() == Some("xboard") {
+                xboard = Some(xboard::Xboard::new());
+            }
+        }
+
+        match &mut xboard {
+            Some(xboard) => xboard::handle_input(
+                line,
+                xboard,
+                &options,
+                &transposition_table,
+                &mut controller,
+            ),
+            None => {
+                board = uci::handle_input(
+                    line,
+                    board,
+                    &mut options,
+                    &transposition_table,
+                    &mut controller,
+                    &mut debug,
+                )
+            }
+        }
     }
+
+    controller.stop();
 }
@@ -1,5 +1,10 @@
 use crate::board::*;
 use crate::piece::*;
+use crate::variant::Variant;
+
+/// The start square of a crazyhouse drop, which carries the dropped piece
+/// in `promotion`.
+pub const DROP: Square = 64;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Move {
@@ -9,6 +14,14 @@ pub struct Move {
 }
 
 impl Move {
+    pub fn drop(typ: PieceType, square: Square) -> Self {
+        Move::new(DROP, square, typ)
+    }
+
+    pub fn is_drop(&self) -> bool {
+        self.start_square == DROP
+    }
+
     pub fn new(start_square: Square, end_square: Square, promotion: PieceType) -> Self {
         Move {
             start_square,
@@ -31,11 +44,20 @@ impl Move {
     }
 
     pub fn as_string(&self) -> String {
+        if self.is_drop() {
+            let piece = Board::converter(Piece {
+                typ: self.promotion,
+                color: Color::White,
+            });
+            return format!("{piece}@{}", self.end_square.as_string());
+        }
+
         let promotion: char = match self.promotion {
             PieceType::Rook => 'r',
             PieceType::Knight => 'n',
             PieceType::Bishop => 'b',
             PieceType::Queen => 'q',
+            PieceType::King => 'k',
             _ => ' ',
         };
 
@@ -54,6 +76,18 @@ impl Move {
     }
 
     pub fn from_string(mov: String) -> Self {
+        if mov.get(1..2) == Some("@") {
+            let typ = match mov.chars().next().unwrap().to_ascii_uppercase() {
+                'P' => PieceType::Pawn,
+                'N' => PieceType::Knight,
+                'B' => PieceType::Bishop,
+                'R' => PieceType::Rook,
+                'Q' => PieceType::Queen,
+                _ => PieceType::Empty,
+            };
+            return Move::drop(typ, mov[2..4].to_string().to_square());
+        }
+
         let start_square = mov[0..2].to_string().to_square();
         let end_square = mov[2..4].to_string().to_square();
 
@@ -63,6 +97,7 @@ impl Move {
                 'n' => PieceType::Knight,
                 'b' => PieceType::Bishop,
                 'q' => PieceType::Queen,
+                'k' => PieceType::King,
                 _ => PieceType::Empty,
             },
             _ => PieceType::Empty,
@@ -79,11 +114,11 @@ impl Board {
     pub fn is_quiet(&mut self, mov: &Move) -> bool {
         let end_piece = self.get_piece(mov.end_square);
 
-        if end_piece.typ != PieceType::Empty {
+        if end_piece.typ != PieceType::Empty && !self.is_castling(mov) {
             return false;
         }
 
-        if mov.promotion != PieceType::Empty {
+        if mov.promotion != PieceType::Empty && !mov.is_drop() {
             return false;
         }
 
@@ -111,6 +146,8 @@ impl Board {
             PieceType::Bishop => self.bishops ^= bitmap,
             PieceType::Rook => self.rooks ^= bitmap,
             PieceType::Queen => self.queens ^= bitmap,
+            // Only in antichess, where the king is an ordinary piece.
+            PieceType::King => self.kings ^= bitmap,
             _ => panic!("Tried to promote to a {:?}!", mov.promotion),
         }
     }
@@ -155,6 +192,10 @@ impl Board {
         let bitmap = (1 << mov.start_square) | (1 << mov.end_square);
 
         self.zobrist_change_square(mov.start_square);
+        self.psqt_remove_square(mov.start_square);
+        self.psqt_remove_square(mov.end_square);
+        self.nnue_remove_square(mov.start_square);
+        self.nnue_remove_square(mov.end_square);
 
         if mov.promotion != PieceType::Empty {
             piece.typ = PieceType::Pawn;
@@ -189,68 +230,12 @@ impl Board {
             PieceType::Bishop => self.bishops ^= bitmap,
             PieceType::Rook => {
                 self.rooks ^= bitmap;
-                match mov.start_square {
-                    0 => {
-                        if self.castling_rights.white_queen {
-                            self.castling_rights.white_queen = false;
-                            self.zobrist ^=
-                                self.zobrist_array[ZobristPosition::WhiteQueenCastle as usize];
-                        }
-                    }
-                    7 => {
-                        if self.castling_rights.white_king {
-                            self.castling_rights.white_king = false;
-                            self.zobrist ^=
-                                self.zobrist_array[ZobristPosition::WhiteKingCastle as usize];
-                        }
-                    }
-                    56 => {
-                        if self.castling_rights.black_queen {
-                            self.castling_rights.black_queen = false;
-                            self.zobrist ^=
-                                self.zobrist_array[ZobristPosition::BlackQueenCastle as usize];
-                        }
-                    }
-                    63 => {
-                        if self.castling_rights.black_king {
-                            self.castling_rights.black_king = false;
-                            self.zobrist ^=
-                                self.zobrist_array[ZobristPosition::BlackKingCastle as usize];
-                        }
-                    }
-                    _ => (),
-                }
+                self.remove_castling_right(mov.start_square);
             }
             PieceType::Queen => self.queens ^= bitmap,
             PieceType::King => {
                 self.kings ^= bitmap;
-                match self.turn {
-                    Color::White => {
-                        if self.castling_rights.white_queen {
-                            self.castling_rights.white_queen = false;
-                            self.zobrist ^=
-                                self.zobrist_array[ZobristPosition::WhiteQueenCastle as usize];
-                        }
-                        if self.castling_rights.white_king {
-                            self.castling_rights.white_king = false;
-                            self.zobrist ^=
-                                self.zobrist_array[ZobristPosition::WhiteKingCastle as usize];
-                        }
-                    }
-                    Color::Black => {
-                        if self.castling_rights.black_queen {
-                            self.castling_rights.black_queen = false;
-                            self.zobrist ^=
-                                self.zobrist_array[ZobristPosition::BlackQueenCastle as usize];
-                        }
-                        if self.castling_rights.black_king {
-                            self.castling_rights.black_king = false;
-                            self.zobrist ^=
-                                self.zobrist_array[ZobristPosition::BlackKingCastle as usize];
-                        }
-                    }
-                    Color::Empty => unreachable!(),
-                }
+                self.clear_castling_rights(self.turn);
             }
             PieceType::Empty => {
                 self.print_board();
@@ -263,38 +248,22 @@ impl Board {
         }
 
         self.zobrist_change_square(mov.end_square);
+        self.psqt_add_square(mov.start_square);
+        self.psqt_add_square(mov.end_square);
+        self.nnue_add_square(mov.start_square);
+        self.nnue_add_square(mov.end_square);
 
-        match mov.end_square {
-            0 => {
-                if self.castling_rights.white_queen {
-                    self.castling_rights.white_queen = false;
-                    self.zobrist ^= self.zobrist_array[ZobristPosition::WhiteQueenCastle as usize];
-                }
-            }
-            7 => {
-                if self.castling_rights.white_king {
-                    self.castling_rights.white_king = false;
-                    self.zobrist ^= self.zobrist_array[ZobristPosition::WhiteKingCastle as usize];
-                }
-            }
-            56 => {
-                if self.castling_rights.black_queen {
-                    self.castling_rights.black_queen = false;
-                    self.zobrist ^= self.zobrist_array[ZobristPosition::BlackQueenCastle as usize];
-                }
-            }
-            63 => {
-                if self.castling_rights.black_king {
-                    self.castling_rights.black_king = false;
-                    self.zobrist ^= self.zobrist_array[ZobristPosition::BlackKingCastle as usize];
-                }
-            }
-            _ => (),
-        }
+        self.remove_castling_right(mov.end_square);
     }
 
     pub fn toggle_piece(&mut self, square: Square, piece: Piece) {
         let bitmap = 1 << square;
+        let removing = (self.white_pieces | self.black_pieces) & bitmap > 0;
+
+        if removing {
+            self.psqt_remove_square(square);
+            self.nnue_remove_square(square);
+        }
 
         match piece.color {
             Color::White => self.white_pieces ^= bitmap,
@@ -321,6 +290,11 @@ impl Board {
                 )
             }
         }
+
+        if !removing {
+            self.psqt_add_square(square);
+            self.nnue_add_square(square);
+        }
     }
 
     pub fn remove_piece(&mut self, square: Square) {
@@ -334,10 +308,21 @@ impl Board {
         let target_square = self.en_passant_target - self.turn as Square;
 
         if piece.typ == PieceType::Pawn && mov.end_square == self.en_passant_target {
-            self.toggle_piece(target_square, self.get_piece(target_square));
+            self.remove_piece(target_square);
         }
     }
 
+    pub fn is_en_passant(&self, mov: &Move) -> bool {
+        mov.end_square == self.en_passant_target
+            && !mov.is_drop()
+            && self.get_piece(mov.start_square).typ == PieceType::Pawn
+    }
+
+    pub fn is_capture(&self, mov: &Move) -> bool {
+        !mov.is_drop()
+            && (self.enemy_pieces() & (1 << mov.end_square) > 0 || self.is_en_passant(mov))
+    }
+
     pub fn restore_en_passant(&mut self, mov: &Move) {
         let piece = self.get_piece(mov.start_square);
         let target_square = self.en_passant_target - self.turn as Square;
@@ -355,33 +340,99 @@ impl Board {
                 Color::Empty => unreachable!(),
             };
             self.toggle_piece(target_square, captured_piece);
+            self.zobrist_change_square(target_square);
         }
     }
 
-    pub fn castle(&mut self, mov: &Move) {
-        let piece = self.get_piece(mov.start_square);
+    /// Castling is written as the king taking its own rook, which stays
+    /// unambiguous in Chess960 where the king may not move at all.
+    pub fn is_castling(&self, mov: &Move) -> bool {
+        if mov.is_drop() {
+            return false;
+        }
+        let king = self.get_piece(mov.start_square);
+        let rook = self.get_piece(mov.end_square);
+        king.typ == PieceType::King && rook.typ == PieceType::Rook && king.color == rook.color
+    }
 
-        if piece.typ == PieceType::King && i32::abs(mov.start_square - mov.end_square) == 2 {
-            match mov.end_square {
-                2 => self.move_piece(&Move::new(0, 3, PieceType::Empty)),
-                6 => self.move_piece(&Move::new(7, 5, PieceType::Empty)),
-                62 => self.move_piece(&Move::new(63, 61, PieceType::Empty)),
-                58 => self.move_piece(&Move::new(56, 59, PieceType::Empty)),
-                _ => unreachable!(),
-            }
+    /// Where the king and the rook of a castling move end up, on the g and
+    /// f or the c and d files.
+    pub fn castling_squares(mov: &Move) -> (Square, Square) {
+        let rank = mov.start_square - mov.start_square.file() as Square;
+        match mov.end_square > mov.start_square {
+            true => (rank + 6, rank + 5),
+            false => (rank + 2, rank + 3),
         }
     }
 
+    pub fn put_piece(&mut self, square: Square, piece: Piece) {
+        self.toggle_piece(square, piece);
+        self.zobrist_change_square(square);
+    }
+
+    pub fn castle(&mut self, mov: &Move) {
+        let (king_square, rook_square) = Self::castling_squares(mov);
+        let king = self.get_piece(mov.start_square);
+        let rook = self.get_piece(mov.end_square);
+
+        self.remove_piece(mov.start_square);
+        self.remove_piece(mov.end_square);
+        self.put_piece(king_square, king);
+        self.put_piece(rook_square, rook);
+        self.clear_castling_rights(self.turn);
+    }
+
     pub fn un_castle(&mut self, mov: &Move) {
-        let piece = self.get_piece(mov.start_square);
+        let (king_square, rook_square) = Self::castling_squares(mov);
+        let king = self.get_piece(king_square);
+        let rook = self.get_piece(rook_square);
+
+        self.remove_piece(king_square);
+        self.remove_piece(rook_square);
+        self.put_piece(mov.start_square, king);
+        self.put_piece(mov.end_square, rook);
+    }
 
-        if piece.typ == PieceType::King && i32::abs(mov.start_square - mov.end_square) == 2 {
-            match mov.end_square {
-                2 => self.move_piece(&Move::new(3, 0, PieceType::Empty)),
-                6 => self.move_piece(&Move::new(5, 7, PieceType::Empty)),
-                62 => self.move_piece(&Move::new(61, 63, PieceType::Empty)),
-                58 => self.move_piece(&Move::new(59, 56, PieceType::Empty)),
-                _ => unreachable!(),
+    fn clear_castling_right(&mut self, position: ZobristPosition) {
+        let right = match position {
+            ZobristPosition::WhiteKingCastle => &mut self.castling_rights.white_king,
+            ZobristPosition::WhiteQueenCastle => &mut self.castling_rights.white_queen,
+            ZobristPosition::BlackKingCastle => &mut self.castling_rights.black_king,
+            ZobristPosition::BlackQueenCastle => &mut self.castling_rights.black_queen,
+            _ => unreachable!(),
+        };
+        if right.take().is_some() {
+            self.zobrist ^= self.zobrist_array[position as usize];
+        }
+    }
+
+    pub fn clear_castling_rights(&mut self, color: Color) {
+        match color {
+            Color::White => {
+                self.clear_castling_right(ZobristPosition::WhiteKingCastle);
+                self.clear_castling_right(ZobristPosition::WhiteQueenCastle);
+            }
+            Color::Black => {
+                self.clear_castling_right(ZobristPosition::BlackKingCastle);
+                self.clear_castling_right(ZobristPosition::BlackQueenCastle);
+            }
+            Color::Empty => unreachable!(),
+        }
+    }
+
+    /// A rook that moves from or is taken on `square` loses its castling
+    /// right.
+    pub fn remove_castling_right(&mut self, square: Square) {
+        let file = Some(square.file() as Square);
+        let rights = self.castling_rights;
+        for (rank, right, position) in [
+            (0, rights.white_king, ZobristPosition::WhiteKingCastle),
+            (0, rights.white_queen, ZobristPosition::WhiteQueenCastle),
+            (7, rights.black_king, ZobristPosition::BlackKingCastle),
+            (7, rights.black_queen, ZobristPosition::BlackQueenCastle),
+        ] {
+            if square.rank() as Square == rank && right == file {
+                self.clear_castling_right(position);
             }
         }
     }
@@ -410,7 +461,14 @@ impl Board {
     }
 
     pub fn make_move(&mut self, mov: &Move) {
-        let captured_piece = self.get_piece(mov.end_square);
+        let castling = self.is_castling(mov);
+        let captured_piece = match castling {
+            true => Piece {
+                typ: PieceType::Empty,
+                color: Color::Empty,
+            },
+            false => self.get_piece(mov.end_square),
+        };
 
         self.irreversible.push(Irreversible {
             en_passant_target: self.en_passant_target,
@@ -418,39 +476,96 @@ impl Board {
             half_move_clock: self.half_move_clock,
             full_move_clock: self.full_move_clock,
             captured_piece,
+            captured_promoted: self.promoted & (1 << mov.end_square) > 0,
+            castling,
+            exploded: Vec::new(),
             mov: mov.clone(),
         });
+        self.nnue_push();
+
+        if mov.is_drop() {
+            if self.en_passant_target != -1 {
+                self.zobrist ^= self.zobrist_array
+                    [ZobristPosition::EnPassant as usize + self.en_passant_target.file() as usize];
+                self.en_passant_target = -1;
+            }
+            self.make_drop(mov);
+            self.half_move_clock = 0;
+            self.change_turn();
+            return;
+        }
+
+        let capture = captured_piece.color != Color::Empty || self.is_en_passant(mov);
+        if self.variant == Variant::Crazyhouse {
+            self.pocket_captures(mov, captured_piece);
+        }
 
         if captured_piece.color != Color::Empty {
             self.remove_piece(mov.end_square);
         }
 
         self.capture_en_passant(mov);
-        self.castle(mov);
         if self.en_passant_target != -1 {
             self.zobrist ^= self.zobrist_array
                 [ZobristPosition::EnPassant as usize + self.en_passant_target.file() as usize];
             self.en_passant_target = -1;
         }
-        self.move_piece(mov);
+        match castling {
+            true => self.castle(mov),
+            false => self.move_piece(mov),
+        }
 
         self.change_half_move_clock(&mov);
+        if self.variant == Variant::Atomic && capture {
+            let exploded = self.explode(mov.end_square);
+            self.irreversible.last_mut().unwrap().exploded = exploded;
+        }
         self.change_turn();
+
+        if self.variant == Variant::ThreeCheck {
+            self.count_check();
+        }
     }
 
     pub fn unmake_move(&mut self, mov: &Move) {
+        // The accumulators of the position before the move are still on
+        // their stack, so they are left out of putting the pieces back.
+        let nnue = self.nnue.take();
+
+        if self.variant == Variant::ThreeCheck {
+            self.uncount_check();
+        }
         self.change_turn();
 
-        let reverse_mov = mov.reverse();
-        self.move_piece(&reverse_mov);
-        self.un_castle(mov);
+        if self.en_passant_target != -1 {
+            self.zobrist ^= self.zobrist_array
+                [ZobristPosition::EnPassant as usize + self.en_passant_target.file() as usize];
+            self.en_passant_target = -1;
+        }
 
+        let last = self.irreversible.last().unwrap().clone();
+        if mov.is_drop() {
+            self.unmake_drop(mov);
+        } else {
+            if self.variant == Variant::Crazyhouse {
+                self.unpocket_captures(mov, &last);
+            }
+            self.unexplode(&last.exploded);
+            match last.castling {
+                true => self.un_castle(mov),
+                false => self.move_piece(&mov.reverse()),
+            }
+        }
+
+        // Moving a pawn back two squares looks like a double push.
         if self.en_passant_target != -1 {
             self.zobrist ^= self.zobrist_array
                 [ZobristPosition::EnPassant as usize + self.en_passant_target.file() as usize];
             self.en_passant_target = -1;
         }
 
+        let castling_rights = self.castling_rights;
+
         let captured_piece;
         Irreversible {
             en_passant_target: self.en_passant_target,
@@ -458,19 +573,81 @@ impl Board {
             half_move_clock: self.half_move_clock,
             full_move_clock: self.full_move_clock,
             captured_piece,
+            captured_promoted: _,
+            castling: _,
+            exploded: _,
             mov: _,
         } = self.irreversible.pop().unwrap();
 
+        for (lost, position) in [
+            (
+                castling_rights.white_king != self.castling_rights.white_king,
+                ZobristPosition::WhiteKingCastle,
+            ),
+            (
+                castling_rights.white_queen != self.castling_rights.white_queen,
+                ZobristPosition::WhiteQueenCastle,
+            ),
+            (
+                castling_rights.black_king != self.castling_rights.black_king,
+                ZobristPosition::BlackKingCastle,
+            ),
+            (
+                castling_rights.black_queen != self.castling_rights.black_queen,
+                ZobristPosition::BlackQueenCastle,
+            ),
+        ] {
+            if lost {
+                self.zobrist ^= self.zobrist_array[position as usize];
+            }
+        }
+
         if self.en_passant_target != -1 {
             self.zobrist ^= self.zobrist_array
                 [ZobristPosition::EnPassant as usize + self.en_passant_target.file() as usize];
-            self.en_passant_target = -1;
         }
 
-        self.restore_en_passant(mov);
+        if !mov.is_drop() {
+            self.restore_en_passant(mov);
+        }
         if captured_piece.color != Color::Empty {
             self.toggle_piece(mov.end_square, captured_piece);
             self.zobrist_change_square(mov.end_square);
         }
+
+        self.nnue = nnue;
+        self.nnue_pop();
+    }
+}
+
+impl Board {
+    /// The move in UCI notation. Castling is written as the king moving two
+    /// squares unless playing Chess960.
+    pub fn move_to_uci(&self, mov: &Move) -> String {
+        if self.chess960 || !self.is_castling(mov) {
+            return mov.as_string();
+        }
+        let (king_square, _) = Self::castling_squares(mov);
+        Move::new(mov.start_square, king_square, PieceType::Empty).as_string()
+    }
+
+    /// A line of moves played from this position in UCI notation.
+    pub fn line_to_uci(&mut self, moves: &[Move]) -> Vec<String> {
+        let mut result = Vec::new();
+        for mov in moves {
+            result.push(self.move_to_uci(mov));
+            self.make_move(mov);
+        }
+        for mov in moves.iter().rev() {
+            self.unmake_move(mov);
+        }
+        result
+    }
+
+    /// The legal move written as `mov`, castling in either notation.
+    pub fn parse_move(&mut self, mov: &str) -> Option<Move> {
+        self.generate_moves()
+            .into_iter()
+            .find(|legal| legal.as_string() == mov || self.move_to_uci(legal) == mov)
     }
 }
@@ -1,8 +1,20 @@
 use crate::board::*;
+use crate::piece::PieceType;
 use crate::r#move::*;
+use crate::variant::Variant;
 
 impl Board {
     pub fn generate_moves(&mut self) -> Vec<Move> {
+        if self.variant != Variant::Standard && self.variant_result().is_some() {
+            return Vec::new();
+        }
+
+        match self.variant {
+            Variant::Atomic => return self.generate_atomic_moves(),
+            Variant::Antichess => return self.generate_antichess_moves(),
+            _ => (),
+        }
+
         let mut moves = Vec::new();
         let own_pieces = self.own_pieces();
         let king = own_pieces & self.kings;
@@ -33,6 +45,35 @@ impl Board {
         moves.append(&mut self.generate_rook_moves(capture_mask | push_mask, pinned));
         moves.append(&mut self.generate_queen_moves(capture_mask | push_mask, pinned));
         moves.append(&mut self.generate_king_moves());
+        if self.variant == Variant::Crazyhouse {
+            moves.append(&mut self.generate_drops(push_mask));
+        }
+        if self.variant == Variant::RacingKings {
+            self.remove_checking_moves(&mut moves);
+        }
+        moves
+    }
+
+    /// The moves of every piece without regard for the own king, with the
+    /// kings stepping onto `king_targets`.
+    pub fn generate_pseudo_legal_moves(&mut self, king_targets: Bitmap) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let own_pieces = self.own_pieces();
+
+        moves.append(&mut self.generate_pawn_moves(Bitmap::MAX, Bitmap::MAX, 0));
+        moves.append(&mut self.generate_knight_moves(Bitmap::MAX, 0));
+        moves.append(&mut self.generate_bishop_moves(Bitmap::MAX, 0));
+        moves.append(&mut self.generate_rook_moves(Bitmap::MAX, 0));
+        moves.append(&mut self.generate_queen_moves(Bitmap::MAX, 0));
+
+        let mut kings = own_pieces & self.kings;
+        while kings > 0 {
+            let start_square = kings.pop_lsb();
+            let mut targets = Self::king_attacks(1 << start_square) & king_targets & !own_pieces;
+            while targets > 0 {
+                moves.push(Move::new(start_square, targets.pop_lsb(), PieceType::Empty));
+            }
+        }
         moves
     }
 }
@@ -0,0 +1,295 @@
+use crate::board::{Board, Color, Square};
+use crate::piece::{Piece, PieceType};
+use crate::psqt::piece_index;
+
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+pub const INPUTS: usize = 768;
+
+/// The size of the hidden layer. The accumulators are arrays of this size,
+/// so a network has to be trained with exactly this many neurons.
+pub const HIDDEN: usize = 256;
+
+type Accumulator = [i16; HIDDEN];
+
+// Quantisation of the hidden layer, the output layer and the scale from the
+// network output to centipawns.
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+/// A 768 -> `HIDDEN` x 2 -> 1 perspective network with a squared clipped
+/// ReLU.
+///
+/// The file is the raw little endian `i16` weights in this order: the
+/// feature weights (`768 * HIDDEN`, grouped by feature), the feature biases
+/// (`HIDDEN`), the output weights (`2 * HIDDEN`, side to move first) and the
+/// output bias. Trailing padding up to 64 bytes is ignored.
+pub struct Network {
+    feature_weights: Vec<i16>,
+    feature_bias: Accumulator,
+    output_weights: Vec<i16>,
+    output_bias: i16,
+}
+
+impl Network {
+    pub fn load(path: &str) -> Result<Network, String> {
+        let bytes =
+            fs::read(path).map_err(|error| format!("Could not read \"{path}\": {error}"))?;
+        let values = bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+
+        let used = HIDDEN * (INPUTS + 3) + 1;
+        if values.len() < used || bytes.len() - 2 * used > 64 || bytes.len() % 2 != 0 {
+            return Err(format!(
+                "\"{path}\" is not a valid network with {HIDDEN} hidden neurons!"
+            ));
+        }
+
+        let (feature_weights, rest) = values.split_at(INPUTS * HIDDEN);
+        let (feature_bias, rest) = rest.split_at(HIDDEN);
+        let (output_weights, rest) = rest.split_at(2 * HIDDEN);
+
+        Ok(Network {
+            feature_weights: feature_weights.to_vec(),
+            feature_bias: feature_bias.try_into().unwrap(),
+            output_weights: output_weights.to_vec(),
+            output_bias: rest[0],
+        })
+    }
+
+    fn feature(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * HIDDEN..(feature + 1) * HIDDEN]
+    }
+
+    /// The evaluation in centipawns for the side whose accumulator is `us`.
+    /// Only plain integer loops, so that the compiler can vectorise them.
+    /// Output weights quantised by `QB` stay far enough below `i16::MAX`
+    /// for the `i32` sums not to overflow.
+    pub fn evaluate(&self, us: &Accumulator, them: &Accumulator) -> i32 {
+        let (own_weights, other_weights) = self.output_weights.split_at(HIDDEN);
+
+        let activate = |accumulator: &[i16], weights: &[i16]| -> i32 {
+            accumulator
+                .iter()
+                .zip(weights)
+                .map(|(value, weight)| {
+                    let value = (*value as i32).clamp(0, QA);
+                    value * value * *weight as i32
+                })
+                .sum()
+        };
+
+        let output = (activate(us, own_weights) + activate(them, other_weights)) / QA
+            + self.output_bias as i32;
+        output * SCALE / (QA * QB)
+    }
+}
+
+/// The hidden layer of the network from white's and from black's point of
+/// view, for every ply since the accumulators were computed from scratch.
+/// Making a move pushes a copy of the last entry that is then updated, and
+/// unmaking it just pops that entry again.
+#[derive(Clone)]
+pub struct Accumulators {
+    network: Arc<Network>,
+    stack: Vec<[Accumulator; 2]>,
+}
+
+impl fmt::Debug for Accumulators {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Accumulators({} plies)", self.stack.len())
+    }
+}
+
+impl PartialEq for Accumulators {
+    fn eq(&self, other: &Accumulators) -> bool {
+        self.current() == other.current()
+    }
+}
+
+impl Eq for Accumulators {}
+
+/// The inputs a piece sets from white's and from black's point of view.
+fn features(piece: Piece, square: Square) -> (usize, usize) {
+    let index = piece_index(piece.typ) * 64;
+    let (white, black) = match piece.color {
+        Color::White => (0, 384),
+        _ => (384, 0),
+    };
+    (
+        white + index + square as usize,
+        black + index + (square ^ 56) as usize,
+    )
+}
+
+impl Accumulators {
+    fn current(&self) -> &[Accumulator; 2] {
+        self.stack.last().unwrap()
+    }
+
+    fn push(&mut self) {
+        self.stack.push(*self.current());
+    }
+
+    fn pop(&mut self) {
+        self.stack.pop();
+    }
+
+    fn add(&mut self, piece: Piece, square: Square) {
+        let (white, black) = features(piece, square);
+        let [white_values, black_values] = self.stack.last_mut().unwrap();
+        for (value, weight) in white_values.iter_mut().zip(self.network.feature(white)) {
+            *value = value.wrapping_add(*weight);
+        }
+        for (value, weight) in black_values.iter_mut().zip(self.network.feature(black)) {
+            *value = value.wrapping_add(*weight);
+        }
+    }
+
+    fn remove(&mut self, piece: Piece, square: Square) {
+        let (white, black) = features(piece, square);
+        let [white_values, black_values] = self.stack.last_mut().unwrap();
+        for (value, weight) in white_values.iter_mut().zip(self.network.feature(white)) {
+            *value = value.wrapping_sub(*weight);
+        }
+        for (value, weight) in black_values.iter_mut().zip(self.network.feature(black)) {
+            *value = value.wrapping_sub(*weight);
+        }
+    }
+}
+
+static NETWORK: Mutex<Option<Arc<Network>>> = Mutex::new(None);
+
+/// Loads the network used by every board created from now on. An empty
+/// `path` or a network that fails to load means the handcrafted evaluation.
+pub fn set_network(path: &str) -> Result<(), String> {
+    let (network, result) = match path {
+        "" => (None, Ok(())),
+        path => match Network::load(path) {
+            Ok(network) => (Some(Arc::new(network)), Ok(())),
+            Err(error) => (None, Err(error)),
+        },
+    };
+    *NETWORK.lock().unwrap() = network;
+    result
+}
+
+pub fn network() -> Option<Arc<Network>> {
+    NETWORK.lock().unwrap().clone()
+}
+
+impl Board {
+    /// Recomputes the accumulators from scratch with the current network.
+    pub fn nnue_init(&mut self) {
+        self.nnue = network().map(|network| Accumulators {
+            stack: vec![[network.feature_bias; 2]],
+            network,
+        });
+
+        for square in 0..64 {
+            self.nnue_add_square(square);
+        }
+    }
+
+    /// Keeps the accumulators of the position before a move, so that the
+    /// move can be unmade by `nnue_pop`.
+    pub fn nnue_push(&mut self) {
+        if let Some(accumulators) = self.nnue.as_mut() {
+            accumulators.push();
+        }
+    }
+
+    pub fn nnue_pop(&mut self) {
+        if let Some(accumulators) = self.nnue.as_mut() {
+            accumulators.pop();
+        }
+    }
+
+    pub fn nnue_add_square(&mut self, square: Square) {
+        if self.nnue.is_none() {
+            return;
+        }
+
+        let piece = self.get_piece(square);
+        if piece.typ != PieceType::Empty {
+            self.nnue.as_mut().unwrap().add(piece, square);
+        }
+    }
+
+    pub fn nnue_remove_square(&mut self, square: Square) {
+        if self.nnue.is_none() {
+            return;
+        }
+
+        let piece = self.get_piece(square);
+        if piece.typ != PieceType::Empty {
+            self.nnue.as_mut().unwrap().remove(piece, square);
+        }
+    }
+
+    /// The network evaluation for the side to move, if a network is loaded.
+    pub fn nnue_eval(&self) -> Option<i32> {
+        self.nnue.as_ref().map(|accumulators| {
+            let [white, black] = accumulators.current();
+            match self.turn {
+                Color::White => accumulators.network.evaluate(white, black),
+                _ => accumulators.network.evaluate(black, white),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USED: usize = HIDDEN * (INPUTS + 3) + 1;
+
+    /// Writes `values` and `padding` zero bytes to a network file of the
+    /// temporary directory.
+    fn write_network(name: &str, values: &[i16], padding: usize) -> String {
+        let mut bytes = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        bytes.resize(bytes.len() + padding, 0);
+        let path = std::env::temp_dir().join(format!("chess_v4_{name}.nnue"));
+        fs::write(&path, bytes).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn loads_the_weights_it_was_given() {
+        let values = (0..USED)
+            .map(|index| (index % 2000) as i16 - 1000)
+            .collect::<Vec<_>>();
+        let path = write_network("round_trip", &values, 64);
+        let network = Network::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let (feature_weights, rest) = values.split_at(INPUTS * HIDDEN);
+        let (feature_bias, rest) = rest.split_at(HIDDEN);
+        let (output_weights, rest) = rest.split_at(2 * HIDDEN);
+        assert_eq!(network.feature_weights, feature_weights);
+        assert_eq!(network.feature_bias, feature_bias);
+        assert_eq!(network.output_weights, output_weights);
+        assert_eq!(network.output_bias, rest[0]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_oversized_files() {
+        let values = vec![1; USED];
+        let path = write_network("truncated", &values[1..], 0);
+        assert!(Network::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+
+        let path = write_network("oversized", &values, 66);
+        assert!(Network::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+use crate::variant::VARIANTS;
+
+use std::collections::HashMap;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum OptionType {
+    Spin {
+        default: i64,
+        min: i64,
+        max: i64,
+    },
+    Check {
+        default: bool,
+    },
+    Combo {
+        default: &'static str,
+        vars: &'static [&'static str],
+    },
+    String {
+        default: &'static str,
+    },
+    Button,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EngineOption {
+    pub name: &'static str,
+    pub typ: OptionType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionValue {
+    Spin(i64),
+    Check(bool),
+    Combo(String),
+    String(String),
+    Button,
+}
+
+pub const OPTIONS: [EngineOption; 19] = [
+    EngineOption {
+        name: "Hash",
+        typ: OptionType::Spin {
+            default: 16,
+            min: 1,
+            max: 4096,
+        },
+    },
+    EngineOption {
+        name: "Threads",
+        typ: OptionType::Spin {
+            default: 1,
+            min: 1,
+            max: 256,
+        },
+    },
+    EngineOption {
+        name: "MultiPV",
+        typ: OptionType::Spin {
+            default: 1,
+            min: 1,
+            max: 256,
+        },
+    },
+    EngineOption {
+        name: "Move Overhead",
+        typ: OptionType::Spin {
+            default: 10,
+            min: 0,
+            max: 5000,
+        },
+    },
+    EngineOption {
+        name: "Clear Hash",
+        typ: OptionType::Button,
+    },
+    EngineOption {
+        name: "Ponder",
+        typ: OptionType::Check { default: false },
+    },
+    EngineOption {
+        name: "UCI_ShowWDL",
+        typ: OptionType::Check { default: false },
+    },
+    EngineOption {
+        name: "UCI_Chess960",
+        typ: OptionType::Check { default: false },
+    },
+    EngineOption {
+        name: "UCI_Variant",
+        typ: OptionType::Combo {
+            default: "chess",
+            vars: &VARIANTS,
+        },
+    },
+    EngineOption {
+        name: "Aspiration Window",
+        typ: OptionType::Spin {
+            default: 25,
+            min: 1,
+            max: 1000,
+        },
+    },
+    EngineOption {
+        name: "Moves To Go",
+        typ: OptionType::Spin {
+            default: 40,
+            min: 1,
+            max: 200,
+        },
+    },
+    EngineOption {
+        name: "EvalFile",
+        typ: OptionType::String { default: "" },
+    },
+    EngineOption {
+        name: "SyzygyPath",
+        typ: OptionType::String { default: "" },
+    },
+    EngineOption {
+        name: "SyzygyProbeDepth",
+        typ: OptionType::Spin {
+            default: 1,
+            min: 1,
+            max: 100,
+        },
+    },
+    EngineOption {
+        name: "SyzygyProbeLimit",
+        typ: OptionType::Spin {
+            default: 7,
+            min: 0,
+            max: 7,
+        },
+    },
+    EngineOption {
+        name: "Syzygy50MoveRule",
+        typ: OptionType::Check { default: true },
+    },
+    EngineOption {
+        name: "OwnBook",
+        typ: OptionType::Check { default: false },
+    },
+    EngineOption {
+        name: "BookFile",
+        typ: OptionType::String { default: "" },
+    },
+    EngineOption {
+        name: "BookBestMove",
+        typ: OptionType::Check { default: false },
+    },
+];
+
+impl EngineOption {
+    pub fn default_value(&self) -> OptionValue {
+        match self.typ {
+            OptionType::Spin { default, .. } => OptionValue::Spin(default),
+            OptionType::Check { default } => OptionValue::Check(default),
+            OptionType::Combo { default, .. } => OptionValue::Combo(default.to_string()),
+            OptionType::String { default } => OptionValue::String(default.to_string()),
+            OptionType::Button => OptionValue::Button,
+        }
+    }
+
+    pub fn as_uci(&self) -> String {
+        match self.typ {
+            OptionType::Spin { default, min, max } => format!(
+                "option name {} type spin default {default} min {min} max {max}",
+                self.name
+            ),
+            OptionType::Check { default } => {
+                format!("option name {} type check default {default}", self.name)
+            }
+            OptionType::Combo { default, vars } => format!(
+                "option name {} type combo default {default}{}",
+                self.name,
+                vars.iter()
+                    .map(|var| format!(" var {var}"))
+                    .collect::<String>()
+            ),
+            OptionType::String { default } => {
+                let default = if default.is_empty() {
+                    "<empty>"
+                } else {
+                    default
+                };
+                format!("option name {} type string default {default}", self.name)
+            }
+            OptionType::Button => format!("option name {} type button", self.name),
+        }
+    }
+
+    pub fn parse(&self, value: &str) -> Result<OptionValue, String> {
+        match self.typ {
+            OptionType::Spin { min, max, .. } => match value.parse::<i64>() {
+                Ok(value) if (min..=max).contains(&value) => Ok(OptionValue::Spin(value)),
+                Ok(value) => Err(format!(
+                    "{value} is out of range for \"{}\" ({min}..{max})",
+                    self.name
+                )),
+                Err(_) => Err(format!("\"{value}\" is not a valid number!")),
+            },
+            OptionType::Check { .. } => match value {
+                "true" => Ok(OptionValue::Check(true)),
+                "false" => Ok(OptionValue::Check(false)),
+                _ => Err(format!("\"{value}\" is not a valid boolean!")),
+            },
+            OptionType::Combo { vars, .. } => {
+                match vars.iter().find(|var| var.eq_ignore_ascii_case(value)) {
+                    Some(var) => Ok(OptionValue::Combo(var.to_string())),
+                    None => Err(format!(
+                        "\"{value}\" is not a valid value for \"{}\"!",
+                        self.name
+                    )),
+                }
+            }
+            OptionType::String { .. } => match value {
+                "<empty>" => Ok(OptionValue::String(String::new())),
+                value => Ok(OptionValue::String(value.to_string())),
+            },
+            OptionType::Button => Ok(OptionValue::Button),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    values: HashMap<&'static str, OptionValue>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Options {
+            values: OPTIONS
+                .iter()
+                .map(|option| (option.name, option.default_value()))
+                .collect(),
+        }
+    }
+
+    pub fn find(name: &str) -> Option<&'static EngineOption> {
+        OPTIONS
+            .iter()
+            .find(|option| option.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Sets the option called `name`, returning the canonical name of the
+    /// option so that the caller can react to it (e.g. buttons).
+    pub fn set(&mut self, name: &str, value: &str) -> Result<&'static str, String> {
+        let option = match Self::find(name) {
+            Some(option) => option,
+            None => return Err(format!("No such option: {name}")),
+        };
+
+        let value = option.parse(value)?;
+        self.values.insert(option.name, value);
+
+        Ok(option.name)
+    }
+
+    pub fn spin(&self, name: &str) -> i64 {
+        match self.values.get(name) {
+            Some(OptionValue::Spin(value)) => *value,
+            value => panic!("\"{name}\" is not a spin option: {value:?}"),
+        }
+    }
+
+    pub fn check(&self, name: &str) -> bool {
+        match self.values.get(name) {
+            Some(OptionValue::Check(value)) => *value,
+            value => panic!("\"{name}\" is not a check option: {value:?}"),
+        }
+    }
+
+    pub fn string(&self, name: &str) -> String {
+        match self.values.get(name) {
+            Some(OptionValue::String(value)) | Some(OptionValue::Combo(value)) => value.clone(),
+            value => panic!("\"{name}\" is not a string option: {value:?}"),
+        }
+    }
+}
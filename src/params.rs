@@ -0,0 +1,189 @@
+use crate::eval::Score;
+
+use std::slice;
+
+/// Every weight of the handcrafted evaluation that the tuner may change.
+/// The piece-square tables and the king attack table are kept fixed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Params {
+    pub material: [Score; 6],
+
+    pub passed_pawn: [Score; 8],
+    pub blocked_passed_pawn: [Score; 8],
+    pub candidate_passer: [Score; 8],
+    pub isolated_pawn: Score,
+    pub doubled_pawn: Score,
+    pub backward_pawn: Score,
+    pub supported_pawn: Score,
+    pub phalanx_pawn: Score,
+
+    pub pawn_shield: [Score; 2],
+    pub missing_shield_pawn: Score,
+    pub pawn_storm: [Score; 5],
+    pub open_file_near_king: Score,
+    pub half_open_file_near_king: Score,
+
+    pub mobility: [Score; 6],
+    pub bishop_pair: Score,
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub rook_on_seventh: Score,
+    pub knight_outpost: Score,
+    pub bishop_outpost: Score,
+    pub trapped_bishop: Score,
+    pub trapped_rook: Score,
+}
+
+include!("weights.rs");
+
+fn format_score(score: &Score) -> String {
+    format!("Score::new({}, {})", score.mg, score.eg)
+}
+
+fn format_scores(scores: &[Score]) -> String {
+    let scores = scores
+        .iter()
+        .map(|score| format!("        {},\n", format_score(score)))
+        .collect::<String>();
+    format!("[\n{scores}    ]")
+}
+
+impl Params {
+    /// The scores by name in a fixed order, and whether they are an array.
+    fn scores(&self) -> Vec<(&'static str, &[Score], bool)> {
+        vec![
+            ("material", &self.material, true),
+            ("passed_pawn", &self.passed_pawn, true),
+            ("blocked_passed_pawn", &self.blocked_passed_pawn, true),
+            ("candidate_passer", &self.candidate_passer, true),
+            ("isolated_pawn", slice::from_ref(&self.isolated_pawn), false),
+            ("doubled_pawn", slice::from_ref(&self.doubled_pawn), false),
+            ("backward_pawn", slice::from_ref(&self.backward_pawn), false),
+            (
+                "supported_pawn",
+                slice::from_ref(&self.supported_pawn),
+                false,
+            ),
+            ("phalanx_pawn", slice::from_ref(&self.phalanx_pawn), false),
+            ("pawn_shield", &self.pawn_shield, true),
+            (
+                "missing_shield_pawn",
+                slice::from_ref(&self.missing_shield_pawn),
+                false,
+            ),
+            ("pawn_storm", &self.pawn_storm, true),
+            (
+                "open_file_near_king",
+                slice::from_ref(&self.open_file_near_king),
+                false,
+            ),
+            (
+                "half_open_file_near_king",
+                slice::from_ref(&self.half_open_file_near_king),
+                false,
+            ),
+            ("mobility", &self.mobility, true),
+            ("bishop_pair", slice::from_ref(&self.bishop_pair), false),
+            (
+                "rook_open_file",
+                slice::from_ref(&self.rook_open_file),
+                false,
+            ),
+            (
+                "rook_semi_open_file",
+                slice::from_ref(&self.rook_semi_open_file),
+                false,
+            ),
+            (
+                "rook_on_seventh",
+                slice::from_ref(&self.rook_on_seventh),
+                false,
+            ),
+            (
+                "knight_outpost",
+                slice::from_ref(&self.knight_outpost),
+                false,
+            ),
+            (
+                "bishop_outpost",
+                slice::from_ref(&self.bishop_outpost),
+                false,
+            ),
+            (
+                "trapped_bishop",
+                slice::from_ref(&self.trapped_bishop),
+                false,
+            ),
+            ("trapped_rook", slice::from_ref(&self.trapped_rook), false),
+        ]
+    }
+
+    fn scores_mut(&mut self) -> Vec<&mut Score> {
+        let mut scores = Vec::new();
+        scores.extend(self.material.iter_mut());
+        scores.extend(self.passed_pawn.iter_mut());
+        scores.extend(self.blocked_passed_pawn.iter_mut());
+        scores.extend(self.candidate_passer.iter_mut());
+        scores.push(&mut self.isolated_pawn);
+        scores.push(&mut self.doubled_pawn);
+        scores.push(&mut self.backward_pawn);
+        scores.push(&mut self.supported_pawn);
+        scores.push(&mut self.phalanx_pawn);
+        scores.extend(self.pawn_shield.iter_mut());
+        scores.push(&mut self.missing_shield_pawn);
+        scores.extend(self.pawn_storm.iter_mut());
+        scores.push(&mut self.open_file_near_king);
+        scores.push(&mut self.half_open_file_near_king);
+        scores.extend(self.mobility.iter_mut());
+        scores.push(&mut self.bishop_pair);
+        scores.push(&mut self.rook_open_file);
+        scores.push(&mut self.rook_semi_open_file);
+        scores.push(&mut self.rook_on_seventh);
+        scores.push(&mut self.knight_outpost);
+        scores.push(&mut self.bishop_outpost);
+        scores.push(&mut self.trapped_bishop);
+        scores.push(&mut self.trapped_rook);
+        scores
+    }
+
+    /// The midgame and endgame value of every score, one after the other.
+    pub fn to_vector(&self) -> Vec<i32> {
+        self.scores()
+            .iter()
+            .flat_map(|(_, scores, _)| scores.iter())
+            .flat_map(|score| [score.mg, score.eg])
+            .collect()
+    }
+
+    pub fn from_vector(vector: &[i32]) -> Params {
+        let mut params = PARAMS;
+        let scores = params.scores_mut();
+        assert_eq!(
+            scores.len() * 2,
+            vector.len(),
+            "Wrong number of parameters!"
+        );
+
+        for (score, values) in scores.into_iter().zip(vector.chunks(2)) {
+            *score = Score::new(values[0], values[1]);
+        }
+        params
+    }
+
+    /// The parameters as the contents of `weights.rs`.
+    pub fn as_rust(&self) -> String {
+        let fields = self
+            .scores()
+            .iter()
+            .map(|(name, scores, array)| match array {
+                true => format!("    {name}: {},\n", format_scores(scores)),
+                false => format!("    {name}: {},\n", format_score(&scores[0])),
+            })
+            .collect::<String>();
+
+        format!(
+            "// Generated by `chess_v4 tune`, edit by hand or tune again.\n\
+             pub const PARAMS: Params = Params {{\n{fields}}};\n"
+        )
+    }
+}
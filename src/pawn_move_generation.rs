@@ -135,6 +135,7 @@ impl Board {
                 let end_square: Square = end_squares.pop_lsb();
 
                 if end_square == self.en_passant_target
+                    && !self.variant.pseudo_legal()
                     && !self.en_passant_check(start_square, end_square)
                 {
                     continue;
@@ -0,0 +1,274 @@
+use crate::board::{BitOperations, Bitmap, Board, Color, Square, SquareOperations};
+use crate::eval::Score;
+use crate::params::{Params, PARAMS};
+
+use std::sync::{Mutex, OnceLock, TryLockError};
+
+const FILE_A: Bitmap = 0x0101010101010101;
+const RANK_1: Bitmap = 0xFF;
+
+const fn generate_file_masks() -> [Bitmap; 8] {
+    let mut result = [0; 8];
+    let mut file = 0;
+    while file < 8 {
+        result[file] = FILE_A << file;
+        file += 1;
+    }
+    result
+}
+
+const fn generate_rank_masks() -> [Bitmap; 8] {
+    let mut result = [0; 8];
+    let mut rank = 0;
+    while rank < 8 {
+        result[rank] = RANK_1 << (rank * 8);
+        rank += 1;
+    }
+    result
+}
+
+pub const FILE_MASKS: [Bitmap; 8] = generate_file_masks();
+pub const RANK_MASKS: [Bitmap; 8] = generate_rank_masks();
+
+const fn generate_adjacent_files() -> [Bitmap; 8] {
+    let mut result = [0; 8];
+    let mut file = 0;
+    while file < 8 {
+        if file > 0 {
+            result[file] |= FILE_MASKS[file - 1];
+        }
+        if file < 7 {
+            result[file] |= FILE_MASKS[file + 1];
+        }
+        file += 1;
+    }
+    result
+}
+
+pub const ADJACENT_FILES: [Bitmap; 8] = generate_adjacent_files();
+
+/// The ranks strictly in front of a rank, from white's and black's side.
+const fn generate_forward_ranks() -> [[Bitmap; 8]; 2] {
+    let mut result = [[0; 8]; 2];
+    let mut rank = 0;
+    while rank < 8 {
+        let mut other = 0;
+        while other < 8 {
+            if other > rank {
+                result[0][rank] |= RANK_MASKS[other];
+            }
+            if other < rank {
+                result[1][rank] |= RANK_MASKS[other];
+            }
+            other += 1;
+        }
+        rank += 1;
+    }
+    result
+}
+
+const FORWARD_RANKS: [[Bitmap; 8]; 2] = generate_forward_ranks();
+
+const fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+        Color::Empty => panic!("The empty color has no pawns!"),
+    }
+}
+
+pub fn relative_rank(square: Square, color: Color) -> usize {
+    match color {
+        Color::White => square.rank() as usize,
+        _ => 7 - square.rank() as usize,
+    }
+}
+
+/// The ranks in front of `square` as seen from `color`.
+pub fn forward_ranks(square: Square, color: Color) -> Bitmap {
+    FORWARD_RANKS[color_index(color)][square.rank() as usize]
+}
+
+/// The squares in front of a pawn on its own and the adjacent files.
+pub fn passed_pawn_mask(square: Square, color: Color) -> Bitmap {
+    let file = square.file() as usize;
+    forward_ranks(square, color) & (FILE_MASKS[file] | ADJACENT_FILES[file])
+}
+
+fn pawn_attacks(pawns: Bitmap, color: Color) -> Bitmap {
+    match color {
+        Color::White => Board::white_pawn_attacks(pawns),
+        _ => Board::black_pawn_attacks(pawns),
+    }
+}
+
+fn stop_square(square: Square, color: Color) -> Square {
+    square + color as Square
+}
+
+/// Scores the pawns of one side from the pawns alone and returns the
+/// score along with its passed pawns.
+fn evaluate_pawns(own: Bitmap, enemy: Bitmap, color: Color, params: &Params) -> (Score, Bitmap) {
+    let other = match color {
+        Color::White => Color::Black,
+        _ => Color::White,
+    };
+
+    let mut score = Score::default();
+    let mut passed = 0;
+
+    let mut pawns = own;
+    while pawns > 0 {
+        let square = pawns.pop_lsb();
+        let file = square.file() as usize;
+        let rank = relative_rank(square, color);
+
+        let forward = forward_ranks(square, color);
+        let adjacent = ADJACENT_FILES[file];
+        let neighbours = own & adjacent;
+
+        // Adjacent pawns on the same rank or behind can still defend it.
+        let behind = neighbours & !forward;
+        let doubled = own & FILE_MASKS[file] & forward > 0;
+        let open_file = enemy & FILE_MASKS[file] & forward == 0;
+        let is_passed = !doubled && enemy & passed_pawn_mask(square, color) == 0;
+
+        if pawn_attacks(own, color) & (1 << square) > 0 {
+            score += params.supported_pawn;
+        }
+        if neighbours & RANK_MASKS[square.rank() as usize] > 0 {
+            score += params.phalanx_pawn;
+        }
+        if doubled {
+            score += params.doubled_pawn;
+        }
+
+        if neighbours == 0 {
+            score += params.isolated_pawn;
+        } else if behind == 0
+            && !is_passed
+            && pawn_attacks(enemy, other) & (1 << stop_square(square, color)) > 0
+        {
+            score += params.backward_pawn;
+        }
+
+        if is_passed {
+            passed |= 1 << square;
+        } else if open_file && !doubled {
+            let helpers = behind.count_ones();
+            let sentries = (enemy & adjacent & forward).count_ones();
+            if helpers >= sentries {
+                score += params.candidate_passer[rank];
+            }
+        }
+    }
+
+    (score, passed)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PawnEntry {
+    key: u64,
+    white: Score,
+    black: Score,
+    passed: Bitmap,
+}
+
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+/// The pawn hash table of the whole process, so that it stays warm from one
+/// search to the next and the search threads share what they computed.
+/// Every slot has its own lock, which is hardly ever contended.
+fn pawn_table() -> &'static [Mutex<PawnEntry>] {
+    static PAWN_TABLE: OnceLock<Vec<Mutex<PawnEntry>>> = OnceLock::new();
+    PAWN_TABLE.get_or_init(|| {
+        (0..PAWN_TABLE_SIZE)
+            .map(|_| Mutex::new(PawnEntry::default()))
+            .collect()
+    })
+}
+
+impl Board {
+    fn evaluate_pawn_entry(&self) -> PawnEntry {
+        let white_pawns = self.pawns & self.white_pieces;
+        let black_pawns = self.pawns & self.black_pieces;
+        let (white, white_passed) = evaluate_pawns(white_pawns, black_pawns, Color::White, &PARAMS);
+        let (black, black_passed) = evaluate_pawns(black_pawns, white_pawns, Color::Black, &PARAMS);
+
+        PawnEntry {
+            key: self.pawn_zobrist,
+            white,
+            black,
+            passed: white_passed | black_passed,
+        }
+    }
+
+    fn probe_pawn_table(&self) -> PawnEntry {
+        let slot = &pawn_table()[self.pawn_zobrist as usize % PAWN_TABLE_SIZE];
+        // A thread that finds the slot taken computes the entry itself
+        // rather than wait.
+        let mut entry = match slot.try_lock() {
+            Ok(entry) => entry,
+            Err(TryLockError::Poisoned(error)) => error.into_inner(),
+            Err(TryLockError::WouldBlock) => return self.evaluate_pawn_entry(),
+        };
+
+        // The key of a board without pawns is 0, which also marks an
+        // empty slot, so both are recomputed.
+        if entry.key != self.pawn_zobrist || entry.key == 0 {
+            *entry = self.evaluate_pawn_entry();
+        }
+        *entry
+    }
+
+    fn passed_pawns(&self, passed: Bitmap, color: Color, params: &Params) -> Score {
+        let occupied = self.white_pieces | self.black_pieces;
+        let mut score = Score::default();
+
+        let mut passed = passed;
+        while passed > 0 {
+            let square = passed.pop_lsb();
+            let rank = relative_rank(square, color);
+            score += if occupied & (1 << stop_square(square, color)) > 0 {
+                params.blocked_passed_pawn[rank]
+            } else {
+                params.passed_pawn[rank]
+            };
+        }
+        score
+    }
+
+    fn own_and_enemy_pawns(&self, color: Color) -> (Bitmap, Bitmap) {
+        let white_pawns = self.pawns & self.white_pieces;
+        let black_pawns = self.pawns & self.black_pieces;
+        match color {
+            Color::White => (white_pawns, black_pawns),
+            _ => (black_pawns, white_pawns),
+        }
+    }
+
+    /// The pawn structure of `color` without the passed pawns. Unlike
+    /// `pawn_structure` this skips the pawn hash table, which only holds
+    /// scores for the default parameters.
+    pub fn pawn_structure_for(&self, color: Color, params: &Params) -> Score {
+        let (own, enemy) = self.own_and_enemy_pawns(color);
+        evaluate_pawns(own, enemy, color, params).0
+    }
+
+    pub fn passed_pawns_for(&self, color: Color, params: &Params) -> Score {
+        let (own, enemy) = self.own_and_enemy_pawns(color);
+        let passed = evaluate_pawns(own, enemy, color, params).1;
+        self.passed_pawns(passed, color, params)
+    }
+
+    /// The pawn structure from white's point of view. Everything that only
+    /// depends on the pawns comes from the pawn hash table, the passed pawns
+    /// are scored here because they also care about blockers.
+    pub fn pawn_structure(&self) -> Score {
+        let entry = self.probe_pawn_table();
+
+        entry.white - entry.black
+            + self.passed_pawns(entry.passed & self.white_pieces, Color::White, &PARAMS)
+            - self.passed_pawns(entry.passed & self.black_pieces, Color::Black, &PARAMS)
+    }
+}
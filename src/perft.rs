@@ -1,5 +1,6 @@
 use crate::board::Board;
 use crate::r#move::Move;
+use crate::variant::Variant;
 
 use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
@@ -15,6 +16,150 @@ use rand::rngs::StdRng;
 
 use rayon::prelude::*;
 
+// Positions with known perft results and the variant they are played in,
+// the standard ones first, then Chess960 positions with castling rights in
+// Shredder-FEN and the other variants.
+pub const PERFT_SUITE: [(Variant, &str, i32, i32); 20] = [
+    (
+        Variant::Standard,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        4,
+        4085603,
+    ),
+    (
+        Variant::Standard,
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        4,
+        422333,
+    ),
+    (
+        Variant::Standard,
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        4,
+        2103487,
+    ),
+    (
+        Variant::Standard,
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        4,
+        326672,
+    ),
+    (
+        Variant::Standard,
+        "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+        4,
+        667366,
+    ),
+    (
+        Variant::Standard,
+        "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+        4,
+        273318,
+    ),
+    (
+        Variant::Standard,
+        "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+        4,
+        382958,
+    ),
+    (
+        Variant::Standard,
+        "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+        4,
+        1171749,
+    ),
+    (
+        Variant::Standard,
+        "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9",
+        4,
+        824055,
+    ),
+    (
+        Variant::Standard,
+        "q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9",
+        4,
+        732757,
+    ),
+    (
+        Variant::Standard,
+        "qbn1brkr/ppp1p1p1/2n4p/3p1p2/P7/6PP/QPPPPP2/1BNNBRKR w HFhf - 0 9",
+        4,
+        465806,
+    ),
+    (
+        Variant::Standard,
+        "qnnbbrkr/1p2ppp1/2pp3p/p7/1P5P/2NP4/P1P1PPP1/Q1NBBRKR w HFhf - 0 9",
+        4,
+        384260,
+    ),
+    (
+        Variant::Standard,
+        "qn1rbbkr/ppp2p1p/1n1pp1p1/8/3P4/P6P/1PP1PPPK/QNNRBB1R w hd - 2 9",
+        4,
+        679699,
+    ),
+    (
+        Variant::Crazyhouse,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+        5,
+        4888832,
+    ),
+    (
+        Variant::ThreeCheck,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 1+2 0 1",
+        3,
+        97862,
+    ),
+    (
+        Variant::KingOfTheHill,
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPPKPPP/RNBQ1B1R w kq - 2 3",
+        4,
+        618058,
+    ),
+    (
+        Variant::RacingKings,
+        "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+        4,
+        296242,
+    ),
+    (
+        Variant::Atomic,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        4,
+        197326,
+    ),
+    (
+        Variant::Atomic,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        3,
+        88298,
+    ),
+    (
+        Variant::Antichess,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        4,
+        153299,
+    ),
+];
+
+/// Runs every position of `PERFT_SUITE`, returns whether all of them give
+/// the expected number of nodes.
+pub fn perft_suite() -> bool {
+    let mut passed = true;
+    for (variant, fen, depth, expected) in PERFT_SUITE {
+        let mut board = Board::from_fen(fen.to_string());
+        board.variant = variant;
+        let nodes = board.perft(depth).values().sum::<i32>();
+        let status = if nodes == expected { "ok" } else { "FAILED" };
+        println!(
+            "{status:6} {} {fen} depth {depth}: {nodes} (expected {expected})",
+            variant.name()
+        );
+        passed &= nodes == expected;
+    }
+    passed
+}
+
 impl Board {
     pub fn perft(&mut self, depth: i32) -> HashMap<String, i32> {
         if depth == 0 {
@@ -24,16 +169,16 @@ impl Board {
         let mut result: HashMap<String, i32> = HashMap::new();
         let moves = self.generate_moves();
         for mut mov in moves {
+            let name = self.move_to_uci(&mov);
             self.make_move(&mut mov);
             let count = self.perft(depth - 1).values().sum();
-            result.insert(mov.as_string(), count);
+            result.insert(name, count);
             self.unmake_move(&mut mov);
         }
 
         result
     }
 
-    #[allow(dead_code)]
     pub fn perft_result(&mut self, depth: i32, moves: &Vec<String>) -> Vec<(String, i32, i32)> {
         let mut stockfish_result: HashMap<String, i32> = HashMap::new();
         let mut fails: Vec<(String, i32, i32)> = Vec::new();
@@ -105,7 +250,6 @@ impl Board {
         fails
     }
 
-    #[allow(dead_code)]
     pub fn perft_test(
         &mut self,
         min_depth: i32,
@@ -141,7 +285,6 @@ impl Board {
         return Ok(());
     }
 
-    #[allow(dead_code)]
     pub fn perft_multi_test(file: &str, depth: i32, seed: Option<u64>, index: Option<usize>) {
         let mut file = File::open(file).unwrap();
         let mut contents = String::new();
@@ -230,7 +373,6 @@ impl Board {
         }
     }
 
-    #[allow(dead_code)]
     pub fn run_perft_multi_test(fen_file: &str, mut depth: i32) {
         let seed;
         let index;
@@ -260,3 +402,120 @@ impl Board {
         Self::perft_multi_test(fen_file, depth, seed, index);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perft(variant: Variant, fen: &str, depth: i32) -> i32 {
+        let mut board = Board::from_fen(fen.to_string());
+        board.variant = variant;
+        board.perft(depth).values().sum()
+    }
+
+    /// Checks the node counts of `fen` from depth 1 up.
+    fn assert_perft(variant: Variant, fen: &str, nodes: &[i32]) {
+        for (depth, expected) in (1..).zip(nodes) {
+            assert_eq!(
+                perft(variant, fen, depth),
+                *expected,
+                "{} {fen} depth {depth}",
+                variant.name()
+            );
+        }
+    }
+
+    #[test]
+    fn chess960_perft() {
+        for (fen, nodes) in [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                [21, 528, 12189],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                [21, 807, 18002],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                [20, 479, 10471],
+            ),
+            (
+                "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+                [22, 593, 13440],
+            ),
+            (
+                "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+                [29, 502, 14569],
+            ),
+        ] {
+            assert_perft(Variant::Standard, fen, &nodes);
+        }
+    }
+
+    #[test]
+    fn three_check_king_of_the_hill_and_racing_kings_perft() {
+        assert_perft(
+            Variant::ThreeCheck,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+            &[20, 400, 8902],
+        );
+        assert_perft(
+            Variant::ThreeCheck,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 1+2 0 1",
+            &[48, 2039, 97862],
+        );
+        assert_perft(
+            Variant::KingOfTheHill,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902],
+        );
+        assert_eq!(
+            perft(
+                Variant::KingOfTheHill,
+                "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPPKPPP/RNBQ1B1R w kq - 2 3",
+                4
+            ),
+            618058
+        );
+        assert_perft(
+            Variant::RacingKings,
+            "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+            &[21, 421, 11264, 296242],
+        );
+    }
+
+    #[test]
+    fn atomic_and_antichess_perft() {
+        assert_perft(
+            Variant::Atomic,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197326],
+        );
+        assert_eq!(
+            perft(
+                Variant::Atomic,
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                3
+            ),
+            88298
+        );
+        // Castles that land next to the enemy king or behind a rook of
+        // the other side.
+        assert_perft(
+            Variant::Atomic,
+            "8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1",
+            &[18, 180, 4364, 61401],
+        );
+        assert_perft(
+            Variant::Atomic,
+            "r3k1rR/5K2/8/8/8/8/8/8 b kq - 0 1",
+            &[25, 282, 6753, 98729],
+        );
+        assert_perft(
+            Variant::Antichess,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            &[20, 400, 8067, 153299],
+        );
+    }
+}
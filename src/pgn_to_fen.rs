@@ -11,11 +11,18 @@ use itertools::Itertools;
 
 use rayon::prelude::*;
 
-#[allow(dead_code)]
 pub fn get_fens_from_pgn(pgn_string: String, seen: &Mutex<HashSet<String>>) {
     let mut seen = seen.lock().unwrap();
     let result = parse_pgn_to_rust_struct(pgn_string.clone());
 
+    // Label every position with the result of the game for the tuner.
+    let label = match result.headers.get("Result").map(String::as_str) {
+        Some("1-0") => " [1.0]",
+        Some("0-1") => " [0.0]",
+        Some("1/2-1/2") => " [0.5]",
+        _ => "",
+    };
+
     for i in result.moves {
         if !seen.contains(&i.fen_after[..(i.fen_after.len() - 3)]) {
             let mut file = OpenOptions::new()
@@ -24,28 +31,41 @@ pub fn get_fens_from_pgn(pgn_string: String, seen: &Mutex<HashSet<String>>) {
                 .open("fens.txt")
                 .unwrap();
 
-            let _ = file.write_fmt(format_args!("{}\n", i.fen_after));
+            let _ = file.write_fmt(format_args!("{}{label}\n", i.fen_after));
             seen.insert(i.fen_after[..(i.fen_after.len() - 3)].to_string());
         }
     }
 }
 
-#[allow(dead_code)]
-pub fn convert_pgn_from_file(pgn_file: &str) {
-    let file = File::open(pgn_file).unwrap();
+/// Splits a PGN file into games, each the headers and the moves.
+pub fn read_games(pgn_file: &str) -> Result<Vec<String>, String> {
+    let file =
+        File::open(pgn_file).map_err(|error| format!("Could not open \"{pgn_file}\": {error}"))?;
 
     let mut buf: Vec<u8> = Vec::new();
-    let _ = BufReader::new(file).read_to_end(&mut buf).unwrap();
+    BufReader::new(file)
+        .read_to_end(&mut buf)
+        .map_err(|error| format!("Could not read \"{pgn_file}\": {error}"))?;
 
-    let games = String::from_utf8(buf)
-        .unwrap()
+    Ok(String::from_utf8(buf)
+        .map_err(|_| format!("\"{pgn_file}\" is not valid UTF-8!"))?
         .split("\n\n")
-        .into_iter()
         .chunks(2)
         .into_iter()
         .map(|chunk| chunk.collect::<Vec<_>>().join("\n\n"))
-        .into_iter()
-        .collect_vec();
+        .collect_vec())
+}
+
+/// Writes every position of the games in `pgn_file` to `fens.txt`,
+/// returns whether the file could be read.
+pub fn convert_pgn_from_file(pgn_file: &str) -> bool {
+    let games = match read_games(pgn_file) {
+        Ok(games) => games,
+        Err(error) => {
+            println!("{error}");
+            return false;
+        }
+    };
 
     let counter = Arc::new(AtomicUsize::new(0));
     let seen = Arc::new(Mutex::new(HashSet::new()));
@@ -58,4 +78,5 @@ pub fn convert_pgn_from_file(pgn_file: &str) {
             println!("{}%", count as f32 * 100f32 / games.len() as f32);
         }
     });
+    true
 }
@@ -0,0 +1,176 @@
+use crate::board::{BitOperations, Bitmap, Board, Color, Square, SquareOperations};
+use crate::eval::Score;
+use crate::params::{Params, PARAMS};
+use crate::pawn_structure::{forward_ranks, relative_rank, ADJACENT_FILES, FILE_MASKS, RANK_MASKS};
+use crate::piece::PieceType;
+use crate::psqt::piece_index;
+
+// The number of squares a piece on an average square reaches, so that a
+// centralised piece scores around zero mobility.
+pub const MOBILITY_BASELINE: [i32; 6] = [0, 4, 6, 7, 13, 0];
+
+// Bishops on a7/h7 that the pawns on b6/g6 shut in, from white's side.
+const TRAPPED_BISHOP_SQUARES: [(Square, Square); 2] = [(48, 41), (55, 46)];
+
+impl Board {
+    fn sides(&self, color: Color) -> (Bitmap, Bitmap) {
+        match color {
+            Color::White => (self.white_pieces, self.black_pieces),
+            _ => (self.black_pieces, self.white_pieces),
+        }
+    }
+
+    fn piece_attacks(&self, typ: PieceType, square: Square, own: Bitmap) -> Bitmap {
+        let occupied = self.white_pieces | self.black_pieces;
+        match typ {
+            PieceType::Knight => Self::knight_attacks(1 << square) & !own,
+            PieceType::Bishop => Self::bishop_attacks(1 << square, occupied, own),
+            PieceType::Rook => Self::rook_attacks(1 << square, occupied, own),
+            PieceType::Queen => Self::queen_attacks(1 << square, occupied, own),
+            _ => 0,
+        }
+    }
+
+    fn pieces_of(&self, typ: PieceType) -> Bitmap {
+        match typ {
+            PieceType::Knight => self.knights,
+            PieceType::Bishop => self.bishops,
+            PieceType::Rook => self.rooks,
+            PieceType::Queen => self.queens,
+            _ => 0,
+        }
+    }
+
+    fn enemy_pawn_attacks(&self, color: Color) -> Bitmap {
+        match color {
+            Color::White => Self::black_pawn_attacks(self.pawns & self.black_pieces),
+            _ => Self::white_pawn_attacks(self.pawns & self.white_pieces),
+        }
+    }
+
+    /// Squares reachable by the pieces of `color` that are not attacked by
+    /// enemy pawns, without generating any moves.
+    pub fn mobility_for(&self, color: Color, params: &Params) -> Score {
+        let (own, _) = self.sides(color);
+        let area = !own & !self.enemy_pawn_attacks(color);
+
+        let mut score = Score::default();
+        for typ in [
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+        ] {
+            let index = piece_index(typ);
+            let mut pieces = self.pieces_of(typ) & own;
+            while pieces > 0 {
+                let square = pieces.pop_lsb();
+                let count = (self.piece_attacks(typ, square, own) & area).count_ones() as i32;
+                score += params.mobility[index] * (count - MOBILITY_BASELINE[index]);
+            }
+        }
+        score
+    }
+
+    pub fn mobility(&self) -> Score {
+        self.mobility_for(Color::White, &PARAMS) - self.mobility_for(Color::Black, &PARAMS)
+    }
+
+    fn is_outpost(&self, square: Square, color: Color) -> bool {
+        let (own, enemy) = self.sides(color);
+        let rank = relative_rank(square, color);
+        let defenders = match color {
+            Color::White => Self::white_pawn_attacks(self.pawns & own),
+            _ => Self::black_pawn_attacks(self.pawns & own),
+        };
+
+        (3..=5).contains(&rank)
+            && defenders & (1 << square) > 0
+            && self.pawns
+                & enemy
+                & ADJACENT_FILES[square.file() as usize]
+                & forward_ranks(square, color)
+                == 0
+    }
+
+    /// Bishop pair, rooks on open files and the seventh rank, outposts and
+    /// trapped pieces for `color`.
+    pub fn piece_activity_for(&self, color: Color, params: &Params) -> Score {
+        let (own, enemy) = self.sides(color);
+        let own_pawns = self.pawns & own;
+        let enemy_pawns = self.pawns & enemy;
+        let king_square = (self.kings & own).lsb();
+
+        let mut score = Score::default();
+
+        if (self.bishops & own).count_ones() >= 2 {
+            score += params.bishop_pair;
+        }
+
+        let mut knights = self.knights & own;
+        while knights > 0 {
+            let square = knights.pop_lsb();
+            if self.is_outpost(square, color) {
+                score += params.knight_outpost;
+            }
+        }
+
+        let mut bishops = self.bishops & own;
+        while bishops > 0 {
+            let square = bishops.pop_lsb();
+            if self.is_outpost(square, color) {
+                score += params.bishop_outpost;
+            }
+
+            let flip = if color == Color::White { 0 } else { 56 };
+            for (bishop, pawn) in TRAPPED_BISHOP_SQUARES {
+                if square == bishop ^ flip && enemy_pawns & (1 << (pawn ^ flip)) > 0 {
+                    score += params.trapped_bishop;
+                }
+            }
+        }
+
+        let mut rooks = self.rooks & own;
+        while rooks > 0 {
+            let square = rooks.pop_lsb();
+            let file = FILE_MASKS[square.file() as usize];
+
+            if own_pawns & file == 0 {
+                score += if enemy_pawns & file == 0 {
+                    params.rook_open_file
+                } else {
+                    params.rook_semi_open_file
+                };
+            }
+
+            // The seventh rank matters when it has pawns to eat or cuts
+            // off the king on the eighth.
+            if relative_rank(square, color) == 6
+                && (enemy_pawns & RANK_MASKS[square.rank() as usize] > 0
+                    || relative_rank((self.kings & enemy).lsb(), color) == 7)
+            {
+                score += params.rook_on_seventh;
+            }
+
+            // A rook next to an uncastled king on the back rank that the
+            // king stops from getting out.
+            let mobility = self
+                .piece_attacks(PieceType::Rook, square, own)
+                .count_ones();
+            if mobility <= 3
+                && relative_rank(square, color) == 0
+                && relative_rank(king_square, color) == 0
+                && (king_square.file() < 4) == (square.file() < king_square.file())
+            {
+                score += params.trapped_rook;
+            }
+        }
+
+        score
+    }
+
+    pub fn piece_activity(&self) -> Score {
+        self.piece_activity_for(Color::White, &PARAMS)
+            - self.piece_activity_for(Color::Black, &PARAMS)
+    }
+}
@@ -0,0 +1,161 @@
+use crate::board::{Board, Color, SquareOperations};
+use crate::piece::{Piece, PieceType};
+use crate::r#move::Move;
+
+impl Board {
+    /// The legal move `mov` in standard algebraic notation, e.g. `Nbxd2+`.
+    pub fn move_to_san(&mut self, mov: &Move) -> String {
+        let mut san = self.move_to_san_without_check(mov);
+
+        self.make_move(mov);
+        if self.is_check() {
+            san.push(match self.generate_moves().is_empty() {
+                true => '#',
+                false => '+',
+            });
+        }
+        self.unmake_move(mov);
+        san
+    }
+
+    fn move_to_san_without_check(&mut self, mov: &Move) -> String {
+        if mov.is_drop() {
+            return mov.as_string();
+        }
+        if self.is_castling(mov) {
+            return match mov.end_square > mov.start_square {
+                true => "O-O".to_string(),
+                false => "O-O-O".to_string(),
+            };
+        }
+
+        let piece = self.get_piece(mov.start_square);
+        let capture = match self.is_capture(mov) {
+            true => "x",
+            false => "",
+        };
+        let promotion = match mov.promotion {
+            PieceType::Empty => String::new(),
+            typ => format!("={}", piece_letter(typ)),
+        };
+
+        if piece.typ == PieceType::Pawn {
+            let file = match capture {
+                "x" => mov.start_square.as_string()[..1].to_string(),
+                _ => String::new(),
+            };
+            return format!("{file}{capture}{}{promotion}", mov.end_square.as_string());
+        }
+
+        // Name the file, the rank or both of the start square when another
+        // piece of the same kind can go to the same square.
+        let rivals = self
+            .generate_moves()
+            .into_iter()
+            .filter(|other| {
+                !other.is_drop()
+                    && other.end_square == mov.end_square
+                    && other.start_square != mov.start_square
+                    && !self.is_castling(other)
+                    && self.get_piece(other.start_square).typ == piece.typ
+            })
+            .collect::<Vec<_>>();
+        let start = mov.start_square.as_string();
+        let disambiguation = if rivals.is_empty() {
+            ""
+        } else if rivals
+            .iter()
+            .all(|other| other.start_square.file() != mov.start_square.file())
+        {
+            &start[..1]
+        } else if rivals
+            .iter()
+            .all(|other| other.start_square.rank() != mov.start_square.rank())
+        {
+            &start[1..]
+        } else {
+            &start
+        };
+
+        format!(
+            "{}{disambiguation}{capture}{}{promotion}",
+            piece_letter(piece.typ),
+            mov.end_square.as_string()
+        )
+    }
+
+    /// The legal move written as `san`, ignoring check marks and
+    /// annotations and allowing more of the start square than needed, as
+    /// in `Qa1a3`. Moves in UCI notation are accepted as well.
+    pub fn parse_san(&mut self, san: &str) -> Option<Move> {
+        let san = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        let moves = self.generate_moves();
+
+        let castling = match san {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+        if let Some(king_side) = castling {
+            return moves.into_iter().find(|mov| {
+                self.is_castling(mov) && (mov.end_square > mov.start_square) == king_side
+            });
+        }
+        if san.contains('@') {
+            let drop = match san.starts_with('@') {
+                true => format!("P{san}"),
+                false => san.to_string(),
+            };
+            return self.parse_move(&drop);
+        }
+
+        let (piece, rest) = match san.chars().next().and_then(letter_piece) {
+            Some(typ) => (typ, &san[1..]),
+            None => (PieceType::Pawn, san),
+        };
+        let (rest, promotion) = match rest.chars().last().and_then(letter_piece) {
+            Some(typ) => (rest[..rest.len() - 1].trim_end_matches('='), typ),
+            None => (rest, PieceType::Empty),
+        };
+        let squares = rest.replace(['x', '-', ':'], "");
+        if squares.len() < 2 || !squares.is_ascii() {
+            return self.parse_move(san);
+        }
+        let (from, to) = squares.split_at(squares.len() - 2);
+
+        let matching = moves
+            .into_iter()
+            .filter(|mov| {
+                let start = mov.start_square.as_string();
+                !mov.is_drop()
+                    && !self.is_castling(mov)
+                    && self.get_piece(mov.start_square).typ == piece
+                    && mov.end_square.as_string() == to
+                    && mov.promotion == promotion
+                    && from.chars().all(|chr| start.contains(chr))
+            })
+            .collect::<Vec<_>>();
+        match matching[..] {
+            [mov] => Some(mov),
+            _ => self.parse_move(san),
+        }
+    }
+}
+
+fn piece_letter(typ: PieceType) -> char {
+    Board::converter(Piece {
+        typ,
+        color: Color::White,
+    })
+}
+
+fn letter_piece(letter: char) -> Option<PieceType> {
+    match letter {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
@@ -2,16 +2,18 @@ use crate::board::{Board, Color};
 use crate::r#move::Move;
 
 use crate::eval::Eval;
+use crate::options::Options;
+use crate::syzygy::{self, Tablebases};
 
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 
+use std::cmp::Reverse;
+use std::mem::size_of;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 pub struct SearchMove {
     pub mov: Move,
     pub eval: Eval,
@@ -22,20 +24,236 @@ pub type Depth = Option<u16>;
 pub type MoveCount = Option<u8>;
 pub type Nodes = Option<usize>;
 pub type Stopper = Arc<AtomicBool>;
+pub type Ponder = Arc<AtomicBool>;
+pub type NodeCounter = Arc<AtomicUsize>;
+
+/// The protocol the search reports its progress in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    #[default]
+    Uci,
+    /// Xboard only wants the thinking output after `post`.
+    Xboard { post: bool },
+    /// Nothing is printed, the caller reports the result.
+    Silent,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchLimits {
+    pub wtime: Time,
+    pub btime: Time,
+    pub winc: Time,
+    pub binc: Time,
+    pub moves_to_go: MoveCount,
+    pub depth: Depth,
+    pub nodes: Nodes,
+    pub mate: MoveCount,
+    pub movetime: Time,
+    pub ponder: bool,
+    /// Search until told to stop, even once the depth runs out.
+    pub infinite: bool,
+    pub protocol: Protocol,
+}
 
-#[derive(Debug, Clone)]
+/// The best move of a position and its eval, found by a search of `depth`
+/// plies during the search numbered `age`.
+#[derive(Debug, Clone, Copy)]
 struct TranspositionEntry {
+    key: u64,
     depth: u16,
-    result: Vec<SearchMove>,
+    age: u8,
+    best: SearchMove,
 }
 
-type TranspositionTable = HashMap<Board, TranspositionEntry>;
+const BUCKET_SIZE: usize = 4;
+
+type Bucket = [Option<TranspositionEntry>; BUCKET_SIZE];
+
+/// A fixed number of buckets of a few entries, each bucket with its own
+/// lock so that threads only wait for each other when they happen to use
+/// the same bucket. A full bucket gives up the entry of an older search or,
+/// failing that, its shallowest entry.
+#[derive(Debug)]
+pub struct TranspositionTable {
+    buckets: Vec<Mutex<Bucket>>,
+    age: AtomicU8,
+}
 
-pub fn timer(time: u64, stopper: &Stopper) {
+/// The table is only locked for writing to resize it. Every search holds a
+/// read lock for as long as it runs.
+pub type SharedTranspositionTable = Arc<RwLock<TranspositionTable>>;
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> Self {
+        let count = usize::max(megabytes * 1024 * 1024 / size_of::<Mutex<Bucket>>(), 1);
+        TranspositionTable {
+            buckets: (0..count)
+                .map(|_| Mutex::new([None; BUCKET_SIZE]))
+                .collect(),
+            age: AtomicU8::new(0),
+        }
+    }
+
+    pub fn resize(&mut self, megabytes: usize) {
+        *self = Self::new(megabytes);
+    }
+
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            *bucket.lock().unwrap() = [None; BUCKET_SIZE];
+        }
+    }
+
+    /// Starts a new search, whose entries replace those of earlier ones
+    /// first.
+    fn new_search(&self) {
+        self.age.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn bucket(&self, key: u64) -> &Mutex<Bucket> {
+        let index = (key as u128 * self.buckets.len() as u128) >> 64;
+        &self.buckets[index as usize]
+    }
+
+    fn get(&self, key: u64) -> Option<TranspositionEntry> {
+        let bucket = self.bucket(key).lock().unwrap();
+        bucket
+            .iter()
+            .flatten()
+            .find(|entry| entry.key == key)
+            .copied()
+    }
+
+    fn insert(&self, key: u64, depth: u16, best: SearchMove) {
+        let age = self.age.load(Ordering::Relaxed);
+        let mut bucket = self.bucket(key).lock().unwrap();
+
+        let same_key = bucket
+            .iter()
+            .position(|entry| entry.is_some_and(|entry| entry.key == key));
+        let slot = match same_key {
+            Some(slot) => {
+                let old = bucket[slot].unwrap();
+                if old.age == age && old.depth > depth {
+                    return;
+                }
+                slot
+            }
+            None => match bucket.iter().position(Option::is_none) {
+                Some(slot) => slot,
+                None => (0..BUCKET_SIZE)
+                    .min_by_key(|slot| {
+                        let entry = bucket[*slot].unwrap();
+                        (entry.age == age, entry.depth)
+                    })
+                    .unwrap(),
+            },
+        };
+
+        bucket[slot] = Some(TranspositionEntry {
+            key,
+            depth,
+            age,
+            best,
+        });
+    }
+}
+
+/// What every node of a search needs besides the position: the options,
+/// the limits, the shared transposition table and tablebases and the
+/// counters of the whole search.
+struct SearchState<'a> {
+    options: &'a Options,
+    limits: &'a SearchLimits,
+    transposition_table: &'a TranspositionTable,
+    tablebases: Option<&'a Tablebases>,
+    stopper: &'a Stopper,
+    nodes: &'a NodeCounter,
+}
+
+impl SearchState<'_> {
+    /// Counts a node, stopping the search once it has searched as many
+    /// nodes as it may.
+    fn count_node(&self) {
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.limits.nodes.is_some_and(|limit| nodes >= limit) {
+            self.stopper.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Whether `eval` is a mate for the side to move as quick as the one
+    /// `go mate` asks for.
+    fn mate_found(&self, eval: Eval) -> bool {
+        match (self.limits.mate, eval.mate) {
+            (Some(moves), Some(mate)) => mate % 2 == 1 && mate.div_ceil(2) <= moves as u16,
+            _ => false,
+        }
+    }
+}
+
+/// Blocks while the engine is pondering. Returns false if the search was
+/// stopped instead of the ponder move being played.
+fn wait_for_ponderhit(pondering: &Ponder, stopper: &Stopper) -> bool {
+    while pondering.load(Ordering::SeqCst) {
+        if stopper.load(Ordering::SeqCst) {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    true
+}
+
+/// The clock only starts running once the opponent has played the move we
+/// are pondering on.
+pub fn timer(time: u64, stopper: &Stopper, pondering: &Ponder) {
+    if !wait_for_ponderhit(pondering, stopper) {
+        return;
+    }
     thread::sleep(Duration::from_millis(time));
     stopper.store(true, Ordering::SeqCst);
 }
 
+fn mate_in_one_more(mut eval: Eval) -> Eval {
+    if let Some(mate) = eval.mate {
+        eval.mate = Some(mate + 1);
+    }
+    eval
+}
+
+/// The bound `eval` of a position from the point of view of the opponent
+/// after the next move, so that `mate_in_one_more(-for_opponent(eval))` is
+/// `eval` again.
+fn for_opponent(eval: Eval) -> Eval {
+    match eval.mate {
+        // Being mated already is the best bound the opponent could have.
+        Some(0) => Eval::MAX,
+        Some(mate) => Eval {
+            score: -eval.score,
+            mate: Some(mate - 1),
+        },
+        None => -eval,
+    }
+}
+
+/// Mate scores and windows that have grown past any realistic evaluation
+/// are searched with a full window instead.
+fn aspiration_window(score: Eval, lower_window: Eval, upper_window: Eval) -> (Eval, Eval) {
+    let limit = Eval::from(100_000i64);
+
+    let alpha = if score.mate.is_some() || lower_window > limit {
+        Eval::MIN
+    } else {
+        score - lower_window
+    };
+    let beta = if score.mate.is_some() || upper_window > limit {
+        Eval::MAX
+    } else {
+        score + upper_window
+    };
+
+    (alpha, beta)
+}
+
 impl Board {
     fn generate_search_moves(&mut self) -> Vec<SearchMove> {
         self.generate_moves()
@@ -49,62 +267,123 @@ impl Board {
 
     pub fn search(
         &mut self,
-        wtime: Time,
-        btime: Time,
-        _winc: Time,
-        _binc: Time,
-        _moves_to_go: MoveCount,
-        max_depth: Depth,
-        _nodes: Nodes,
-        _mate: MoveCount,
-        movetime: Time,
+        limits: &SearchLimits,
+        pondering: &Ponder,
+        options: &Options,
+        transposition_table: &SharedTranspositionTable,
         stopper: &Stopper,
+        nodes: &NodeCounter,
     ) -> Vec<SearchMove> {
-        stopper.store(false, Ordering::SeqCst);
-        let time = match movetime {
-            Some(time) => Some(time),
-            None => None,
-        };
-        match time {
-            Some(time) => {
-                let stopper_clone = Arc::clone(stopper);
-                thread::spawn(move || timer(time, &stopper_clone));
-            }
-            None => (),
+        let move_overhead = options.spin("Move Overhead") as u64;
+        let moves_to_go = limits
+            .moves_to_go
+            .map_or(options.spin("Moves To Go") as u64, u64::from);
+
+        if let Some(time) = limits.movetime {
+            let time = u64::max(time.saturating_sub(move_overhead), 1);
+            let stopper_clone = Arc::clone(stopper);
+            let pondering_clone = Arc::clone(pondering);
+            thread::spawn(move || timer(time, &stopper_clone, &pondering_clone));
         }
-        let max_depth = match max_depth {
-            Some(depth) => depth,
-            None => u16::MAX,
-        };
+        // A mate in n moves is found within 2n - 1 plies.
+        let max_depth = limits.depth.unwrap_or(u16::MAX).min(
+            limits
+                .mate
+                .map_or(u16::MAX, |moves| (moves as u16 * 2).saturating_sub(1)),
+        );
 
-        match self.turn {
-            Color::White => match wtime {
-                Some(white_time) => {
-                    let stopper_clone = Arc::clone(stopper);
-                    thread::spawn(move || timer(white_time / 40, &stopper_clone));
-                }
-                None => (),
-            },
-            Color::Black => match btime {
-                Some(black_time) => {
-                    let stopper_clone = Arc::clone(stopper);
-                    thread::spawn(move || timer(black_time / 40, &stopper_clone));
-                }
-                None => (),
-            },
+        let (time_left, increment) = match self.turn {
+            Color::White => (limits.wtime, limits.winc),
+            Color::Black => (limits.btime, limits.binc),
             Color::Empty => unreachable!(),
+        };
+        if let Some(time_left) = time_left {
+            // The increment comes back after the move, but the clock has to
+            // last until then.
+            let time_left = time_left.saturating_sub(move_overhead);
+            let time = (time_left / moves_to_go + increment.unwrap_or(0)).min(time_left);
+            let time = u64::max(time, 1);
+            let stopper_clone = Arc::clone(stopper);
+            let pondering_clone = Arc::clone(pondering);
+            thread::spawn(move || timer(time, &stopper_clone, &pondering_clone));
         }
 
+        let start = Instant::now();
+        syzygy::reset_tb_hits();
+
+        let transposition_table = transposition_table.read().unwrap();
+        transposition_table.new_search();
+        let tablebases = syzygy::tablebases();
+        let state = SearchState {
+            options,
+            limits,
+            transposition_table: &transposition_table,
+            tablebases: tablebases.as_deref(),
+            stopper,
+            nodes,
+        };
+
+        thread::scope(|scope| {
+            for thread in 1..options.spin("Threads") as usize {
+                let mut board = self.clone();
+                let state = &state;
+                scope.spawn(move || board.iterative_deepening(max_depth, state, thread, None));
+            }
+
+            let moves =
+                self.iterative_deepening(max_depth, &state, 0, Some((start, limits.protocol)));
+
+            // A ponder search must not report its move before the opponent
+            // has made theirs, even if it ran out of depth.
+            wait_for_ponderhit(pondering, stopper);
+
+            // Nor may an infinite search before it is stopped.
+            while limits.infinite && !stopper.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            // The helper threads only stop when they are told to.
+            stopper.store(true, Ordering::SeqCst);
+
+            moves
+        })
+    }
+
+    /// Runs the iterative deepening loop. Only the main thread, which is
+    /// given the start time of the search and the protocol to use, reports
+    /// its progress. The helper threads start at other depths and with the
+    /// root moves in another order, so that they fill the transposition
+    /// table with other positions than the main thread instead of
+    /// repeating its work.
+    fn iterative_deepening(
+        &mut self,
+        max_depth: u16,
+        state: &SearchState,
+        thread: usize,
+        report: Option<(Instant, Protocol)>,
+    ) -> Vec<SearchMove> {
+        let stopper = state.stopper;
+        let window = Eval::from(state.options.spin("Aspiration Window"));
+        let multi_pv = state.options.spin("MultiPV") as usize;
+
         let mut alpha = Eval::MIN;
         let mut beta = Eval::MAX;
 
         let mut depth = 1;
 
-        let mut transposition_table = HashMap::new();
         let mut moves = self.generate_search_moves();
+        if moves.is_empty() {
+            return self.search_root(depth, alpha, beta, moves, state);
+        }
+
+        if thread > 0 {
+            depth += (thread % 2) as u16;
+            let rotation = thread % moves.len();
+            moves.rotate_left(rotation);
+        }
 
-        let mut lower_window = Eval::from(25i64);
-        let mut upper_window = Eval::from(25i64);
+        let mut lower_window = window;
+        let mut upper_window = window;
 
         let mut previous_score = alpha;
 
@@ -113,57 +392,248 @@ impl Board {
                 return moves;
             }
 
-            let transposition_clone = transposition_table.clone();
+            if multi_pv > 1 {
+                let mut remaining = moves.clone();
+                let mut result = Vec::new();
 
-            let result = self.negamax(
-                depth,
-                alpha,
-                beta,
-                moves.clone(),
-                &mut transposition_table,
-                stopper,
-            );
-            let best_move = result[0].mov;
-            let score = result[0].eval;
-
-            if best_move == Move::null() || score == alpha {
-                if score == beta {
-                    upper_window = upper_window * Eval::from(4i64);
-                } else if score == alpha {
-                    lower_window = lower_window * Eval::from(4i64);
+                while result.len() < multi_pv && !remaining.is_empty() {
+                    let pv =
+                        self.search_root(depth, Eval::MIN, Eval::MAX, remaining.clone(), state);
+                    if stopper.load(Ordering::SeqCst) {
+                        return moves;
+                    }
+
+                    remaining.retain(|search_move| search_move.mov != pv[0].mov);
+                    result.push(pv[0].clone());
                 }
 
-                alpha = previous_score - lower_window;
-                beta = previous_score + upper_window;
+                result.append(&mut remaining);
+                moves = result;
+            } else {
+                let result = self.search_root(depth, alpha, beta, moves.clone(), state);
+                if stopper.load(Ordering::SeqCst) {
+                    return moves;
+                }
 
-                transposition_table = transposition_clone;
-                continue;
-            }
+                let best_move = result[0].mov;
+                let score = result[0].eval;
+
+                if best_move == Move::null() || score == alpha {
+                    if score == beta {
+                        upper_window = upper_window * Eval::from(4i64);
+                    } else if score == alpha {
+                        lower_window = lower_window * Eval::from(4i64);
+                    }
+
+                    (alpha, beta) = aspiration_window(previous_score, lower_window, upper_window);
+
+                    continue;
+                }
 
-            lower_window = Eval::from(25i64);
-            upper_window = Eval::from(25i64);
+                lower_window = window;
+                upper_window = window;
 
-            alpha = score - lower_window;
-            beta = score + upper_window;
+                (alpha, beta) = aspiration_window(score, lower_window, upper_window);
+
+                previous_score = score;
+
+                moves = result;
+            }
+
+            match report {
+                Some((start, Protocol::Uci)) => self.print_info(depth, &moves, state, start),
+                Some((start, Protocol::Xboard { post: true })) => {
+                    self.print_thinking(depth, &moves, state, start)
+                }
+                _ => (),
+            }
 
-            previous_score = score;
+            if report.is_some() && state.mate_found(moves[0].eval) {
+                break;
+            }
 
-            moves = result;
             depth += 1;
         }
 
         moves
     }
 
+    fn print_info(
+        &mut self,
+        depth: u16,
+        moves: &[SearchMove],
+        state: &SearchState,
+        start: Instant,
+    ) {
+        let multi_pv = state.options.spin("MultiPV") as usize;
+        let nodes = state.nodes.load(Ordering::Relaxed);
+        let time = start.elapsed().as_millis();
+        let nps = (nodes as u128 * 1000) / u128::max(time, 1);
+
+        for (index, SearchMove { mov, eval }) in moves.iter().take(multi_pv).enumerate() {
+            if *mov == Move::null() {
+                continue;
+            }
+
+            let wdl = if state.options.check("UCI_ShowWDL") {
+                let (win, draw, loss) = eval.wdl();
+                format!(" wdl {win} {draw} {loss}")
+            } else {
+                String::new()
+            };
+
+            let pv = self.principal_variation(mov, depth as usize, state.transposition_table);
+            let pv = self.line_to_uci(&pv).join(" ");
+
+            let tb_hits = match state.tablebases {
+                Some(_) => format!(" tbhits {}", syzygy::tb_hits()),
+                None => String::new(),
+            };
+
+            println!(
+                "info depth {depth} multipv {} score {}{wdl} nodes {nodes} nps {nps}{tb_hits} time {time} pv {pv}",
+                index + 1,
+                eval.as_uci(),
+            );
+        }
+    }
+
+    /// Prints the best line in the xboard format of depth, score, time in
+    /// centiseconds and nodes.
+    fn print_thinking(
+        &mut self,
+        depth: u16,
+        moves: &[SearchMove],
+        state: &SearchState,
+        start: Instant,
+    ) {
+        let SearchMove { mov, eval } = &moves[0];
+        if *mov == Move::null() {
+            return;
+        }
+
+        let nodes = state.nodes.load(Ordering::Relaxed);
+        let time = start.elapsed().as_millis() / 10;
+
+        let pv = self.principal_variation(mov, depth as usize, state.transposition_table);
+        let pv = self.line_to_uci(&pv).join(" ");
+
+        println!("{depth} {} {time} {nodes} {pv}", eval.as_xboard());
+    }
+
+    /// Follows the best moves stored in the transposition table, starting
+    /// with `mov`.
+    pub fn principal_variation(
+        &mut self,
+        mov: &Move,
+        max_length: usize,
+        transposition_table: &TranspositionTable,
+    ) -> Vec<Move> {
+        let mut pv = vec![*mov];
+        let mut seen = vec![self.zobrist];
+        self.make_move(mov);
+
+        while pv.len() < max_length && !seen.contains(&self.zobrist) {
+            seen.push(self.zobrist);
+
+            let next = match transposition_table.get(self.zobrist) {
+                Some(entry) => entry.best.mov,
+                None => break,
+            };
+
+            if next == Move::null() || !self.generate_moves().contains(&next) {
+                break;
+            }
+
+            self.make_move(&next);
+            pv.push(next);
+        }
+
+        for mov in pv.iter().rev() {
+            self.unmake_move(mov);
+        }
+
+        pv
+    }
+
+    fn search_root(
+        &mut self,
+        depth: u16,
+        mut alpha: Eval,
+        beta: Eval,
+        moves: Vec<SearchMove>,
+        state: &SearchState,
+    ) -> Vec<SearchMove> {
+        if moves.is_empty() {
+            return vec![SearchMove {
+                mov: Move::null(),
+                eval: self.eval(moves.len()),
+            }];
+        }
+
+        // Only the moves that keep the outcome the tablebases promise are
+        // searched, so that a won position is not thrown away.
+        let moves = match state
+            .tablebases
+            .and_then(|tablebases| tablebases.root_moves(self, &moves))
+        {
+            Some(kept) => kept,
+            None => moves,
+        };
+
+        let mut result = Vec::new();
+
+        for SearchMove { mov, eval: _ } in moves {
+            self.make_move(&mov);
+            let moves = self.generate_search_moves();
+
+            let score = mate_in_one_more(
+                -self.negamax(
+                    depth - 1,
+                    for_opponent(beta),
+                    for_opponent(alpha),
+                    moves,
+                    state,
+                )[0]
+                .eval,
+            );
+
+            self.unmake_move(&mov);
+
+            if score >= beta {
+                return vec![SearchMove {
+                    mov: Move::null(),
+                    eval: beta,
+                }];
+            }
+
+            if score > alpha {
+                alpha = score;
+            }
+
+            result.push(SearchMove { mov, eval: score });
+        }
+
+        result.sort_by_key(|search_move| Reverse(search_move.eval));
+
+        result
+    }
+
     fn negamax(
         &mut self,
         depth: u16,
         mut alpha: Eval,
         beta: Eval,
         moves: Vec<SearchMove>,
-        transposition_table: &mut TranspositionTable,
-        stopper: &Stopper,
+        state: &SearchState,
     ) -> Vec<SearchMove> {
+        let SearchState {
+            transposition_table,
+            stopper,
+            ..
+        } = *state;
+        state.count_node();
+
         if moves.len() == 0 {
             return vec![SearchMove {
                 mov: Move::null(),
@@ -178,53 +648,69 @@ impl Board {
             }];
         }
 
+        // Every move scores at least as well as being mated right now, so
+        // such a bound always fails high.
+        if beta.mate == Some(0) {
+            return vec![SearchMove {
+                mov: Move::null(),
+                eval: beta,
+            }];
+        }
+
         if depth == 0 {
-            return self.quiescence_search(alpha, beta, moves, stopper);
+            return self.quiescence_search(alpha, beta, moves, state);
         }
 
-        match transposition_table.get(self) {
-            Some(entry) => {
-                if entry.depth >= depth + 1 {
-                    if entry.result[0].eval.mate != None {
-                        return entry.result.clone();
-                    }
-                    if entry.result[0].eval <= alpha {
-                        return vec![SearchMove {
-                            mov: Move::null(),
-                            eval: alpha.into(),
-                        }];
-                    }
-                    if entry.result[0].eval >= beta {
-                        return vec![SearchMove {
-                            mov: Move::null(),
-                            eval: beta.into(),
-                        }];
-                    }
-                    return entry.result.clone();
+        if let Some(eval) = state
+            .tablebases
+            .and_then(|tablebases| self.probe_tablebases(tablebases, depth))
+        {
+            return vec![SearchMove {
+                mov: Move::null(),
+                eval,
+            }];
+        }
+
+        if let Some(entry) = transposition_table.get(self.zobrist) {
+            if entry.depth > depth {
+                if entry.best.eval.mate.is_some() {
+                    return vec![entry.best];
+                }
+                if entry.best.eval <= alpha {
+                    return vec![SearchMove {
+                        mov: Move::null(),
+                        eval: alpha,
+                    }];
                 }
+                if entry.best.eval >= beta {
+                    return vec![SearchMove {
+                        mov: Move::null(),
+                        eval: beta,
+                    }];
+                }
+                return vec![entry.best];
             }
-            None => (),
         }
 
+        let original_alpha = alpha;
         let mut result = Vec::new();
 
         for SearchMove { mov, eval: _ } in moves {
-            let mut board = self.clone();
-
-            board.make_move(&mov);
-            let moves = board.generate_search_moves();
+            self.make_move(&mov);
+            let moves = self.generate_search_moves();
 
-            let score = -board.negamax(
-                depth - 1,
-                -beta,
-                -alpha,
-                moves,
-                transposition_table,
-                stopper,
-            )[0]
-            .eval;
+            let score = mate_in_one_more(
+                -self.negamax(
+                    depth - 1,
+                    for_opponent(beta),
+                    for_opponent(alpha),
+                    moves,
+                    state,
+                )[0]
+                .eval,
+            );
 
-            board.unmake_move(&mov);
+            self.unmake_move(&mov);
 
             if score >= beta {
                 let result = vec![SearchMove {
@@ -252,24 +738,13 @@ impl Board {
             return result;
         }
 
-        result.sort_by(
-            |SearchMove {
-                 mov: _,
-                 eval: eval1,
-             },
-             SearchMove {
-                 mov: _,
-                 eval: eval2,
-             }| { eval2.cmp(eval1) },
-        );
+        result.sort_by_key(|search_move| Reverse(search_move.eval));
 
-        transposition_table.insert(
-            self.clone(),
-            TranspositionEntry {
-                depth,
-                result: result.clone(),
-            },
-        );
+        // A search that never raised alpha only knows an upper bound, which
+        // must not be mistaken for an exact result by later probes.
+        if alpha > original_alpha && !stopper.load(Ordering::SeqCst) {
+            transposition_table.insert(self.zobrist, depth, result[0]);
+        }
 
         result
     }
@@ -279,9 +754,11 @@ impl Board {
         mut alpha: Eval,
         beta: Eval,
         moves: Vec<SearchMove>,
-        stopper: &Stopper,
+        state: &SearchState,
     ) -> Vec<SearchMove> {
-        if stopper.load(Ordering::SeqCst) {
+        state.count_node();
+
+        if state.stopper.load(Ordering::SeqCst) {
             return vec![SearchMove {
                 mov: Move::null(),
                 eval: beta.into(),
@@ -293,10 +770,7 @@ impl Board {
         if stand_pat.mate != None {
             return vec![SearchMove {
                 mov: Move::null(),
-                eval: Eval {
-                    score: stand_pat.score,
-                    mate: Some(stand_pat.mate.unwrap() + 1),
-                },
+                eval: stand_pat,
             }];
         }
 
@@ -321,7 +795,10 @@ impl Board {
             self.make_move(&mov);
             let moves = self.generate_search_moves();
 
-            let score = -self.quiescence_search(-beta, -alpha, moves, stopper)[0].eval;
+            let score = mate_in_one_more(
+                -self.quiescence_search(for_opponent(beta), for_opponent(alpha), moves, state)[0]
+                    .eval,
+            );
 
             self.unmake_move(&mov);
 
@@ -351,16 +828,7 @@ impl Board {
             return result;
         }
 
-        result.sort_by(
-            |SearchMove {
-                 mov: _,
-                 eval: eval1,
-             },
-             SearchMove {
-                 mov: _,
-                 eval: eval2,
-             }| { eval2.cmp(eval1) },
-        );
+        result.sort_by_key(|search_move| Reverse(search_move.eval));
 
         result
     }
@@ -0,0 +1,126 @@
+use crate::board::Board;
+use crate::options::Options;
+use crate::r#move::Move;
+use crate::search::{Ponder, SearchLimits, SharedTranspositionTable, Stopper};
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use std::thread::{self, JoinHandle};
+
+/// Owns the thread of the running search. Every search gets its own
+/// stopper, so timers left over from an earlier search can not stop a
+/// later one.
+pub struct SearchController {
+    handle: Option<JoinHandle<()>>,
+    stopper: Stopper,
+    pondering: Ponder,
+    /// Set when the move of the search is no longer wanted.
+    aborted: Arc<AtomicBool>,
+}
+
+impl SearchController {
+    pub fn new() -> Self {
+        SearchController {
+            handle: None,
+            stopper: Arc::new(AtomicBool::new(true)),
+            pondering: Arc::new(AtomicBool::new(false)),
+            aborted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Starts searching `board`, stopping the previous search first so that
+    /// every `go` is answered by exactly one `bestmove`.
+    pub fn start(
+        &mut self,
+        board: Board,
+        limits: SearchLimits,
+        options: Options,
+        transposition_table: SharedTranspositionTable,
+    ) {
+        let table = Arc::clone(&transposition_table);
+        self.start_with(
+            board,
+            limits,
+            options,
+            transposition_table,
+            move |board, mov| {
+                if *mov == Move::null() {
+                    println!("bestmove (none)");
+                    return;
+                }
+
+                let pv = board.principal_variation(mov, 2, &table.read().unwrap());
+                match &board.line_to_uci(&pv)[..] {
+                    [best_move, ponder_move] => {
+                        println!("bestmove {best_move} ponder {ponder_move}")
+                    }
+                    _ => println!("bestmove {}", board.move_to_uci(mov)),
+                }
+            },
+        );
+    }
+
+    /// Starts searching `board` like `start`, but hands the move found to
+    /// `report` on the search thread instead of printing it. `report` is
+    /// not called for a search that was aborted.
+    pub fn start_with(
+        &mut self,
+        mut board: Board,
+        limits: SearchLimits,
+        options: Options,
+        transposition_table: SharedTranspositionTable,
+        report: impl FnOnce(&mut Board, &Move) + Send + 'static,
+    ) {
+        self.stop();
+
+        self.stopper = Arc::new(AtomicBool::new(false));
+        self.pondering = Arc::new(AtomicBool::new(limits.ponder));
+        self.aborted = Arc::new(AtomicBool::new(false));
+
+        let stopper = Arc::clone(&self.stopper);
+        let pondering = Arc::clone(&self.pondering);
+        let aborted = Arc::clone(&self.aborted);
+        self.handle = Some(thread::spawn(move || {
+            let result = board.search(
+                &limits,
+                &pondering,
+                &options,
+                &transposition_table,
+                &stopper,
+                &Arc::new(AtomicUsize::new(0)),
+            )[0];
+
+            if !aborted.load(Ordering::SeqCst) {
+                report(&mut board, &result.mov);
+            }
+        }));
+    }
+
+    /// Stops the running search, if any, and waits for it to report its
+    /// best move.
+    pub fn stop(&mut self) {
+        self.pondering.store(false, Ordering::SeqCst);
+        self.stopper.store(true, Ordering::SeqCst);
+        self.wait();
+    }
+
+    /// Stops the running search without reporting a move, unless it was
+    /// reported before the search could be stopped.
+    pub fn abort(&mut self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.stop();
+    }
+
+    /// Waits for the running search, if any, to finish on its own and
+    /// report its move.
+    pub fn wait(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::SeqCst);
+    }
+}
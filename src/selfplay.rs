@@ -0,0 +1,641 @@
+use crate::board::{Board, Color};
+use crate::epd::EpdPosition;
+use crate::eval::Eval;
+use crate::options::Options;
+use crate::r#move::Move;
+use crate::search::{Protocol, SearchLimits, SharedTranspositionTable, TranspositionTable};
+use crate::sprt::{Sprt, SprtModel, SprtStatus};
+use crate::variant::Variant;
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+pub const SELFPLAY_GAMES: usize = 2;
+pub const SELFPLAY_MOVETIME: u64 = 100;
+pub const SELFPLAY_PGN: &str = "selfplay.pgn";
+
+/// A side that has been at least this many centipawns behind for
+/// `RESIGN_PLIES` plies in a row, by the evals of both engines, loses.
+pub const RESIGN_SCORE: i64 = 600;
+const RESIGN_PLIES: usize = 8;
+
+/// From move `DRAW_MOVE_NUMBER` on, a game whose evals stayed within this
+/// many centipawns of zero for `DRAW_PLIES` plies in a row is drawn.
+pub const DRAW_SCORE: i64 = 10;
+const DRAW_PLIES: usize = 16;
+const DRAW_MOVE_NUMBER: usize = 40;
+
+/// Games that get this long are drawn.
+const MAX_PLIES: usize = 600;
+const FIFTY_MOVES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    pub fn as_pgn(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+
+    /// The points the side playing `color` scored.
+    pub fn score(&self, color: Color) -> f64 {
+        match (self, color) {
+            (GameResult::Draw, _) => 0.5,
+            (GameResult::WhiteWins, Color::White) | (GameResult::BlackWins, Color::Black) => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    fn win_for(color: Color) -> GameResult {
+        match color {
+            Color::White => GameResult::WhiteWins,
+            _ => GameResult::BlackWins,
+        }
+    }
+}
+
+impl Board {
+    /// The result and why, if the side to move has no moves left or the
+    /// variant has ended the game.
+    pub fn game_over(&mut self) -> Option<(GameResult, &'static str)> {
+        if !self.generate_moves().is_empty() {
+            return None;
+        }
+
+        let reason = if self.variant_result().is_some() {
+            "variant rules"
+        } else if self.is_check() {
+            "checkmate"
+        } else {
+            "no legal moves"
+        };
+        let opponent = match self.turn {
+            Color::White => Color::Black,
+            _ => Color::White,
+        };
+        let result = match self.eval(0).mate {
+            Some(mate) if mate % 2 == 1 => GameResult::win_for(self.turn),
+            Some(_) => GameResult::win_for(opponent),
+            None => GameResult::Draw,
+        };
+        Some((result, reason))
+    }
+}
+
+/// How one side of a match plays: this engine with its own options, or an
+/// external engine spoken to over UCI.
+pub struct PlayerSettings {
+    pub name: String,
+    /// The options set before the match, e.g. `("Hash", "32")`. Options
+    /// that load files, such as `EvalFile`, are global for this engine
+    /// and can't differ between two internal players.
+    pub options: Vec<(String, String)>,
+    pub engine: Option<String>,
+}
+
+pub struct MatchSettings {
+    pub games: usize,
+    /// The fens the games start from, each played once with either colour.
+    pub openings: Vec<String>,
+    pub limits: SearchLimits,
+    pub variant: Variant,
+    pub first: PlayerSettings,
+    pub second: PlayerSettings,
+    pub pgn: String,
+    /// Adjudication thresholds in centipawns, 0 turns them off.
+    pub resign_score: i64,
+    pub draw_score: i64,
+    /// The test that ends the match early once it is decided, `games`
+    /// then being the most games played.
+    pub sprt: Option<Sprt>,
+}
+
+enum Player {
+    Internal {
+        name: String,
+        options: Options,
+        transposition_table: SharedTranspositionTable,
+    },
+    External {
+        name: String,
+        process: Child,
+        input: ChildStdin,
+        output: BufReader<ChildStdout>,
+    },
+}
+
+/// A move as it goes into the pgn.
+struct PlayedMove {
+    san: String,
+    /// The eval of the side that moved, if its engine gave one.
+    eval: Option<Eval>,
+    time: u128,
+}
+
+struct Game {
+    white: String,
+    black: String,
+    fen: String,
+    moves: Vec<PlayedMove>,
+    result: GameResult,
+    reason: String,
+}
+
+/// Reads the score of a UCI `info` line, e.g. `score cp 35` or
+/// `score mate -2`, into our plies to mate.
+fn parse_uci_score(line: &str) -> Option<Eval> {
+    let mut words = line.split_whitespace().skip_while(|word| *word != "score");
+    words.next()?;
+    let kind = words.next()?;
+    let value = words.next()?.parse::<i64>().ok()?;
+    match kind {
+        "cp" => Some(Eval::from(value)),
+        "mate" => Some(Eval {
+            score: 0,
+            mate: Some(match value > 0 {
+                true => (2 * value - 1) as u16,
+                false => (-2 * value) as u16,
+            }),
+        }),
+        _ => None,
+    }
+}
+
+/// The eval as a pgn comment, e.g. `+0.35` or `-M2`.
+fn pgn_eval(eval: &Eval) -> String {
+    match eval.mate {
+        Some(mate) if mate % 2 == 1 => format!("+M{}", mate.div_ceil(2)),
+        Some(mate) => format!("-M{}", mate / 2),
+        None => format!("{:+.2}", eval.score as f64 / 100.0),
+    }
+}
+
+impl Player {
+    fn new(settings: &PlayerSettings, variant: Variant) -> Result<Player, String> {
+        let path = match &settings.engine {
+            Some(path) => path,
+            None => {
+                let mut options = Options::new();
+                for (name, value) in &settings.options {
+                    options.set(name, value)?;
+                }
+                let transposition_table = Arc::new(RwLock::new(TranspositionTable::new(
+                    options.spin("Hash") as usize,
+                )));
+                return Ok(Player::Internal {
+                    name: settings.name.clone(),
+                    options,
+                    transposition_table,
+                });
+            }
+        };
+
+        let mut process = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|error| format!("Could not start \"{path}\": {error}"))?;
+        let input = process.stdin.take().unwrap();
+        let output = BufReader::new(process.stdout.take().unwrap());
+        let mut player = Player::External {
+            name: settings.name.clone(),
+            process,
+            input,
+            output,
+        };
+
+        player.send("uci")?;
+        let id = player.wait_for("uciok")?;
+        for (name, value) in &settings.options {
+            player.send(&format!("setoption name {name} value {value}"))?;
+        }
+        if variant != Variant::Standard {
+            player.send(&format!(
+                "setoption name UCI_Variant value {}",
+                variant.name()
+            ))?;
+        }
+        player.send("isready")?;
+        player.wait_for("readyok")?;
+
+        // Without a name of our own the engine's is used.
+        if let (Player::External { name, .. }, Some(id)) = (&mut player, id) {
+            if name.is_empty() {
+                *name = id;
+            }
+        }
+        Ok(player)
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Player::Internal { name, .. } | Player::External { name, .. } => name,
+        }
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        if let Player::External { input, name, .. } = self {
+            writeln!(input, "{command}").map_err(|error| format!("{name}: {error}"))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the output of an external engine up to the line starting with
+    /// `token`, returning the name it gave with `id name` on the way.
+    fn wait_for(&mut self, token: &str) -> Result<Option<String>, String> {
+        let (output, name) = match self {
+            Player::External { output, name, .. } => (output, name),
+            Player::Internal { .. } => return Ok(None),
+        };
+
+        let mut id = None;
+        loop {
+            let mut line = String::new();
+            match output.read_line(&mut line) {
+                Ok(0) | Err(_) => return Err(format!("{name} quit before \"{token}\"")),
+                Ok(_) => (),
+            }
+            if let Some(engine) = line.trim().strip_prefix("id name ") {
+                id = Some(engine.to_string());
+            }
+            if line.split_whitespace().next() == Some(token) {
+                return Ok(id);
+            }
+        }
+    }
+
+    fn new_game(&mut self) -> Result<(), String> {
+        match self {
+            Player::Internal {
+                transposition_table,
+                ..
+            } => {
+                transposition_table.read().unwrap().clear();
+                Ok(())
+            }
+            Player::External { .. } => {
+                self.send("ucinewgame")?;
+                self.send("isready")?;
+                self.wait_for("readyok").map(|_| ())
+            }
+        }
+    }
+
+    /// Finds the move to play in `board`, which was reached from `fen`
+    /// by `moves`.
+    fn think(
+        &mut self,
+        board: &Board,
+        fen: &str,
+        moves: &[String],
+        limits: &SearchLimits,
+    ) -> Result<(Move, Option<Eval>), String> {
+        if let Player::Internal {
+            options,
+            transposition_table,
+            ..
+        } = self
+        {
+            let limits = SearchLimits {
+                protocol: Protocol::Silent,
+                ..*limits
+            };
+            let result = board.clone().search(
+                &limits,
+                &Arc::new(AtomicBool::new(false)),
+                options,
+                transposition_table,
+                &Arc::new(AtomicBool::new(false)),
+                &Arc::new(AtomicUsize::new(0)),
+            );
+            return match result.first() {
+                Some(best) if best.mov != Move::null() => Ok((best.mov, Some(best.eval))),
+                _ => Err(format!("{} found no move", self.name())),
+            };
+        }
+
+        let position = match moves.is_empty() {
+            true => format!("position fen {fen}"),
+            false => format!("position fen {fen} moves {}", moves.join(" ")),
+        };
+        self.send(&position)?;
+        match (limits.depth, limits.movetime) {
+            (Some(depth), _) => self.send(&format!("go depth {depth}"))?,
+            (None, Some(movetime)) => self.send(&format!("go movetime {movetime}"))?,
+            (None, None) => self.send("go")?,
+        }
+
+        let Player::External { output, name, .. } = self else {
+            unreachable!()
+        };
+        let mut eval = None;
+        loop {
+            let mut line = String::new();
+            match output.read_line(&mut line) {
+                Ok(0) | Err(_) => return Err(format!("{name} quit while thinking")),
+                Ok(_) => (),
+            }
+            if line.starts_with("info") {
+                eval = parse_uci_score(&line).or(eval);
+            }
+            if let Some(rest) = line.strip_prefix("bestmove") {
+                let uci = rest.split_whitespace().next().unwrap_or("");
+                return match board.clone().parse_move(uci) {
+                    Some(mov) => Ok((mov, eval)),
+                    None => Err(format!("{name} played the illegal move \"{uci}\"")),
+                };
+            }
+        }
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        if let Player::External { process, input, .. } = self {
+            let _ = writeln!(input, "quit");
+            let _ = process.wait();
+        }
+    }
+}
+
+/// Checks the adjudication rules against the evals of the plies played so
+/// far, each from white's point of view.
+fn adjudicate(
+    evals: &[Option<i64>],
+    settings: &MatchSettings,
+) -> Option<(GameResult, &'static str)> {
+    let recent = |plies: usize| -> Option<Vec<i64>> {
+        if evals.len() < plies {
+            return None;
+        }
+        evals[evals.len() - plies..].iter().copied().collect()
+    };
+
+    if settings.resign_score > 0 {
+        if let Some(recent) = recent(RESIGN_PLIES) {
+            if recent.iter().all(|eval| *eval >= settings.resign_score) {
+                return Some((GameResult::WhiteWins, "resignation"));
+            }
+            if recent.iter().all(|eval| *eval <= -settings.resign_score) {
+                return Some((GameResult::BlackWins, "resignation"));
+            }
+        }
+    }
+
+    if settings.draw_score > 0 && evals.len() >= DRAW_MOVE_NUMBER * 2 {
+        if let Some(recent) = recent(DRAW_PLIES) {
+            if recent.iter().all(|eval| eval.abs() <= settings.draw_score) {
+                return Some((GameResult::Draw, "draw adjudication"));
+            }
+        }
+    }
+    None
+}
+
+fn play_game(
+    white: &mut Player,
+    black: &mut Player,
+    fen: &str,
+    settings: &MatchSettings,
+) -> Result<Game, String> {
+    white.new_game()?;
+    black.new_game()?;
+
+    let mut board = Board::from_fen(fen.to_string());
+    board.variant = settings.variant;
+
+    let mut uci_moves = Vec::new();
+    let mut played = Vec::new();
+    let mut evals = Vec::new();
+    let mut positions = vec![board.zobrist];
+
+    let (result, reason) = loop {
+        if let Some((result, reason)) = board.game_over() {
+            break (result, reason.to_string());
+        }
+        if positions
+            .iter()
+            .filter(|key| **key == board.zobrist)
+            .count()
+            >= 3
+        {
+            break (GameResult::Draw, "threefold repetition".to_string());
+        }
+        if board.half_move_clock as usize >= FIFTY_MOVES {
+            break (GameResult::Draw, "fifty move rule".to_string());
+        }
+        if played.len() >= MAX_PLIES {
+            break (GameResult::Draw, "maximum game length".to_string());
+        }
+        if let Some((result, reason)) = adjudicate(&evals, settings) {
+            break (result, reason.to_string());
+        }
+
+        let turn = board.turn;
+        let player = match turn {
+            Color::White => &mut *white,
+            _ => &mut *black,
+        };
+        let start = Instant::now();
+        let (mov, eval) = match player.think(&board, fen, &uci_moves, &settings.limits) {
+            Ok(result) => result,
+            Err(error) => {
+                let opponent = match turn {
+                    Color::White => Color::Black,
+                    _ => Color::White,
+                };
+                break (GameResult::win_for(opponent), format!("forfeit: {error}"));
+            }
+        };
+        let time = start.elapsed().as_millis();
+
+        let sign = match turn {
+            Color::White => 1,
+            _ => -1,
+        };
+        evals.push(eval.map(|eval| eval.as_xboard() * sign));
+        played.push(PlayedMove {
+            san: board.move_to_san(&mov),
+            eval,
+            time,
+        });
+        uci_moves.push(board.move_to_uci(&mov));
+        board.make_move(&mov);
+        positions.push(board.zobrist);
+    };
+
+    Ok(Game {
+        white: white.name().to_string(),
+        black: black.name().to_string(),
+        fen: fen.to_string(),
+        moves: played,
+        result,
+        reason,
+    })
+}
+
+/// The game in PGN, with the eval and time of every move as a comment.
+fn game_to_pgn(game: &Game, round: usize, variant: Variant) -> String {
+    let board = Board::from_fen(game.fen.clone());
+    let mut pgn = String::new();
+    let mut header = |name: &str, value: &str| pgn += &format!("[{name} \"{value}\"]\n");
+
+    header("Event", "chess_v4 selfplay");
+    header("Site", "?");
+    header("Round", &round.to_string());
+    header("White", &game.white);
+    header("Black", &game.black);
+    header("Result", game.result.as_pgn());
+    if variant != Variant::Standard {
+        header("Variant", variant.name());
+    }
+    if game.fen != Board::new().to_fen() {
+        header("SetUp", "1");
+        header("FEN", &game.fen);
+    }
+    header("PlyCount", &game.moves.len().to_string());
+    header("Termination", &game.reason);
+    pgn.push('\n');
+
+    let mut move_number = usize::max(board.full_move_clock as usize, 1);
+    let mut turn = board.turn;
+    let mut tokens = Vec::new();
+    for (index, mov) in game.moves.iter().enumerate() {
+        match turn {
+            Color::White => tokens.push(format!("{move_number}.")),
+            _ if index == 0 => tokens.push(format!("{move_number}...")),
+            _ => (),
+        }
+        tokens.push(mov.san.clone());
+        tokens.push(match &mov.eval {
+            Some(eval) => format!("{{{} {}ms}}", pgn_eval(eval), mov.time),
+            None => format!("{{{}ms}}", mov.time),
+        });
+
+        if turn == Color::Black {
+            move_number += 1;
+        }
+        turn = match turn {
+            Color::White => Color::Black,
+            _ => Color::White,
+        };
+    }
+    tokens.push(format!("{{{}}}", game.reason));
+    tokens.push(game.result.as_pgn().to_string());
+
+    // Movetext lines are kept within 80 characters.
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + token.len() + 1 > 80 {
+            pgn += &line;
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line += &token;
+    }
+    pgn + &line + "\n\n"
+}
+
+/// Reads the openings of a file of fens or epd lines.
+pub fn read_openings(path: &str) -> Result<Vec<String>, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|error| format!("Could not open \"{path}\": {error}"))?;
+    let mut openings = Vec::new();
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        let position =
+            EpdPosition::parse(line).ok_or_else(|| format!("\"{line}\" is not a valid opening"))?;
+        Board::try_from_fen(&position.fen)?;
+        openings.push(position.fen);
+    }
+    Ok(openings)
+}
+
+/// Plays the games of the match, every opening once with either colour,
+/// and writes them to the pgn file, until the SPRT is decided. Returns the points the first player
+/// scored in every game, in order.
+pub fn run_match(settings: &MatchSettings) -> Result<Vec<f64>, String> {
+    let mut first = Player::new(&settings.first, settings.variant)?;
+    let mut second = Player::new(&settings.second, settings.variant)?;
+    let file = File::create(&settings.pgn)
+        .map_err(|error| format!("Could not create \"{}\": {error}", settings.pgn))?;
+    let mut pgn = BufWriter::new(file);
+
+    let start_position = [Board::new().to_fen()];
+    let openings = match settings.openings.is_empty() {
+        true => &start_position[..],
+        false => &settings.openings[..],
+    };
+
+    let mut scores = Vec::new();
+    for round in 0..settings.games {
+        let fen = &openings[(round / 2) % openings.len()];
+        let first_is_white = round % 2 == 0;
+        let game = match first_is_white {
+            true => play_game(&mut first, &mut second, fen, settings)?,
+            false => play_game(&mut second, &mut first, fen, settings)?,
+        };
+
+        let first_color = match first_is_white {
+            true => Color::White,
+            false => Color::Black,
+        };
+        scores.push(game.result.score(first_color));
+
+        pgn.write_all(game_to_pgn(&game, round + 1, settings.variant).as_bytes())
+            .and_then(|_| pgn.flush())
+            .map_err(|error| format!("Could not write \"{}\": {error}", settings.pgn))?;
+
+        let wins = scores.iter().filter(|score| **score == 1.0).count();
+        let losses = scores.iter().filter(|score| **score == 0.0).count();
+        let draws = scores.len() - wins - losses;
+        println!(
+            "Game {}/{}: {} vs {}: {} {{{}}}",
+            round + 1,
+            settings.games,
+            game.white,
+            game.black,
+            game.result.as_pgn(),
+            game.reason
+        );
+        println!(
+            "Score of {} vs {}: {wins} - {losses} - {draws} [{:.3}]",
+            first.name(),
+            second.name(),
+            scores.iter().sum::<f64>() / scores.len() as f64
+        );
+
+        // A pentanomial test only stops between pairs of games.
+        let sprt = match settings.sprt {
+            Some(sprt) if sprt.model == SprtModel::Trinomial || scores.len() % 2 == 0 => sprt,
+            _ => continue,
+        };
+        println!("{}", sprt.report(&scores));
+        match sprt.status(&scores) {
+            SprtStatus::Continue => (),
+            SprtStatus::AcceptH0 => {
+                println!("H0 accepted after {} games", scores.len());
+                break;
+            }
+            SprtStatus::AcceptH1 => {
+                println!("H1 accepted after {} games", scores.len());
+                break;
+            }
+        }
+    }
+    Ok(scores)
+}
@@ -0,0 +1,454 @@
+use crate::board::Board;
+use crate::eval::Eval;
+use crate::options::Options;
+use crate::r#move::Move;
+use crate::search::{
+    Protocol, SearchLimits, SharedTranspositionTable, Stopper, TranspositionTable,
+};
+use crate::variant::Variant;
+
+use crossbeam::channel::{self, Receiver, Sender};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Instant;
+
+pub const SERVE_PORT: u16 = 8080;
+
+/// Requests bigger than this are refused rather than read.
+const MAX_BODY: usize = 1 << 16;
+
+/// How long a principal variation of a search without a depth may be.
+const MAX_PV_LENGTH: usize = 64;
+
+/// The deepest perft a request may ask for. Perft can not be cancelled, so
+/// deeper ones would hold up the queue for minutes or more.
+const MAX_PERFT_DEPTH: i32 = 6;
+
+type Response = (u16, Value);
+
+/// The fields every request may give to set up the position.
+#[derive(Debug, Deserialize)]
+struct PositionRequest {
+    fen: Option<String>,
+    variant: Option<String>,
+    #[serde(default)]
+    moves: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnalyseRequest {
+    #[serde(flatten)]
+    position: PositionRequest,
+    depth: Option<u16>,
+    movetime: Option<u64>,
+    multipv: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PerftRequest {
+    #[serde(flatten)]
+    position: PositionRequest,
+    depth: i32,
+}
+
+/// The slow requests, which wait in the queue for their turn.
+enum Work {
+    Analyse {
+        limits: SearchLimits,
+        options: Options,
+    },
+    Perft {
+        depth: i32,
+    },
+}
+
+struct Job {
+    board: Board,
+    work: Work,
+    /// The value of `Server::generation` when the job was queued, so that
+    /// a later cancel can tell it apart.
+    generation: usize,
+    reply: Sender<Response>,
+}
+
+/// Answers the requests of all connections, running the searches one at a
+/// time on a worker thread.
+struct Server {
+    queue: Sender<Job>,
+    /// The stopper of the running search.
+    stopper: Mutex<Stopper>,
+    /// Counts the cancels. Queued jobs of an older generation are dropped.
+    generation: AtomicUsize,
+    transposition_table: SharedTranspositionTable,
+}
+
+fn error(status: u16, message: impl Into<String>) -> Response {
+    (status, json!({ "error": message.into() }))
+}
+
+fn score_json(eval: &Eval) -> Value {
+    match eval.mate {
+        Some(mate) if mate % 2 == 1 => json!({ "mate": mate.div_ceil(2) }),
+        Some(mate) => json!({ "mate": -((mate / 2) as i64) }),
+        None => json!({ "cp": eval.score }),
+    }
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, Response> {
+    serde_json::from_str(body).map_err(|err| error(400, format!("invalid request: {err}")))
+}
+
+/// Sets up the position of a request.
+fn parse_position(position: &PositionRequest) -> Result<Board, Response> {
+    let mut board = match &position.fen {
+        Some(fen) => Board::try_from_fen(fen).map_err(|err| error(400, err))?,
+        None => Board::new(),
+    };
+
+    if let Some(name) = &position.variant {
+        board.variant = Variant::from_name(name);
+        if board.variant.name() != name.to_ascii_lowercase() {
+            return Err(error(400, format!("unknown variant {name}")));
+        }
+    }
+    if !board.has_kings() {
+        return Err(error(400, "the position needs one king of each colour"));
+    }
+
+    for mov in &position.moves {
+        match board.parse_move(mov) {
+            Some(legal_move) => board.make_move(&legal_move),
+            None => return Err(error(400, format!("illegal move {mov}"))),
+        }
+    }
+    Ok(board)
+}
+
+impl Server {
+    fn new() -> (Self, Receiver<Job>) {
+        let (queue, jobs) = channel::unbounded();
+        let options = Options::new();
+        let server = Server {
+            queue,
+            stopper: Mutex::new(Arc::new(AtomicBool::new(false))),
+            generation: AtomicUsize::new(0),
+            transposition_table: Arc::new(RwLock::new(TranspositionTable::new(
+                options.spin("Hash") as usize,
+            ))),
+        };
+        (server, jobs)
+    }
+
+    /// Runs the queued jobs in order, skipping those cancelled while they
+    /// waited. A job that panics fails on its own, the worker goes on with
+    /// the next one.
+    fn work(&self, jobs: Receiver<Job>) {
+        for job in jobs {
+            if job.generation < self.generation.load(Ordering::SeqCst) {
+                let _ = job.reply.send(error(503, "cancelled"));
+                continue;
+            }
+
+            let response = panic::catch_unwind(AssertUnwindSafe(|| match job.work {
+                Work::Analyse { limits, options } => {
+                    self.analyse(job.board, &limits, &options, job.generation)
+                }
+                Work::Perft { depth } => perft(job.board, depth),
+            }))
+            .unwrap_or_else(|_| error(500, "the job failed"));
+            let _ = job.reply.send(response);
+        }
+    }
+
+    fn analyse(
+        &self,
+        mut board: Board,
+        limits: &SearchLimits,
+        options: &Options,
+        generation: usize,
+    ) -> Response {
+        let stopper = Arc::new(AtomicBool::new(false));
+        *self.stopper.lock().unwrap() = Arc::clone(&stopper);
+        let cancelled = || self.generation.load(Ordering::SeqCst) > generation;
+
+        // A cancel that came after the job was taken from the queue may
+        // have stopped the previous stopper instead.
+        if cancelled() {
+            stopper.store(true, Ordering::SeqCst);
+        }
+
+        let nodes = Arc::new(AtomicUsize::new(0));
+        let start = Instant::now();
+        let moves = board.search(
+            limits,
+            &Arc::new(AtomicBool::new(false)),
+            options,
+            &self.transposition_table,
+            &stopper,
+            &nodes,
+        );
+        let time = start.elapsed().as_millis();
+
+        let multi_pv = options.spin("MultiPV") as usize;
+        let pv_length = limits.depth.map_or(MAX_PV_LENGTH, usize::from);
+        let transposition_table = self.transposition_table.read().unwrap();
+        let lines = moves
+            .iter()
+            .take(multi_pv)
+            .filter(|search_move| search_move.mov != Move::null())
+            .map(|search_move| {
+                let pv =
+                    board.principal_variation(&search_move.mov, pv_length, &transposition_table);
+                json!({
+                    "move": board.move_to_uci(&search_move.mov),
+                    "score": score_json(&search_move.eval),
+                    "pv": board.line_to_uci(&pv),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let best_move = lines.first().map(|line| line["move"].clone());
+        (
+            200,
+            json!({
+                "bestmove": best_move,
+                "lines": lines,
+                "nodes": nodes.load(Ordering::Relaxed),
+                "time": time,
+                "cancelled": cancelled(),
+            }),
+        )
+    }
+
+    /// Stops the running search, which still answers with what it found,
+    /// and drops every queued job.
+    fn cancel(&self) -> Response {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.stopper.lock().unwrap().store(true, Ordering::SeqCst);
+        (200, json!({ "cancelled": true }))
+    }
+
+    /// Queues the job and waits for its turn.
+    fn enqueue(&self, board: Board, work: Work) -> Response {
+        let (reply, response) = channel::bounded(1);
+        let job = Job {
+            board,
+            work,
+            generation: self.generation.load(Ordering::SeqCst),
+            reply,
+        };
+        if self.queue.send(job).is_err() {
+            return error(503, "the worker has stopped");
+        }
+        response
+            .recv()
+            .unwrap_or_else(|_| error(500, "the job failed"))
+    }
+
+    fn route(&self, method: &str, path: &str, body: &str) -> Response {
+        if method != "POST" {
+            return error(405, format!("{method} is not supported, use POST"));
+        }
+
+        let result = match path {
+            "/analyse" => parse_body::<AnalyseRequest>(body).and_then(|request| {
+                let board = parse_position(&request.position)?;
+                if request.depth.is_none() && request.movetime.is_none() {
+                    return Err(error(400, "analyse needs a depth or a movetime"));
+                }
+
+                let mut options = Options::new();
+                if let Some(multi_pv) = request.multipv {
+                    options
+                        .set("MultiPV", &multi_pv.to_string())
+                        .map_err(|err| error(400, err))?;
+                }
+                let limits = SearchLimits {
+                    depth: request.depth,
+                    movetime: request.movetime,
+                    protocol: Protocol::Silent,
+                    ..Default::default()
+                };
+                Ok(self.enqueue(board, Work::Analyse { limits, options }))
+            }),
+            "/perft" => parse_body::<PerftRequest>(body).and_then(|request| {
+                let board = parse_position(&request.position)?;
+                if !(1..=MAX_PERFT_DEPTH).contains(&request.depth) {
+                    return Err(error(
+                        400,
+                        format!("perft needs a depth from 1 to {MAX_PERFT_DEPTH}"),
+                    ));
+                }
+                Ok(self.enqueue(
+                    board,
+                    Work::Perft {
+                        depth: request.depth,
+                    },
+                ))
+            }),
+            "/legal-moves" => parse_body::<PositionRequest>(body).and_then(|request| {
+                let mut board = parse_position(&request)?;
+                let moves = board
+                    .generate_moves()
+                    .iter()
+                    .map(|mov| board.move_to_uci(mov))
+                    .collect::<Vec<_>>();
+                Ok((200, json!({ "moves": moves })))
+            }),
+            "/eval" => parse_body::<PositionRequest>(body).and_then(|request| {
+                let mut board = parse_position(&request)?;
+                let moves = board.generate_moves().len();
+                let eval = board.eval(moves);
+                Ok((200, json!({ "score": score_json(&eval) })))
+            }),
+            "/cancel" => Ok(self.cancel()),
+            _ => Err(error(404, format!("no endpoint {path}"))),
+        };
+        result.unwrap_or_else(|response| response)
+    }
+
+    fn handle(&self, stream: TcpStream) {
+        let response = match read_request(&stream) {
+            Ok((method, path, body)) => self.route(&method, &path, &body),
+            Err(response) => response,
+        };
+        write_response(stream, response);
+    }
+}
+
+fn perft(mut board: Board, depth: i32) -> Response {
+    let start = Instant::now();
+    let result = board.perft(depth);
+    (
+        200,
+        json!({
+            "nodes": result.values().map(|&nodes| nodes as u64).sum::<u64>(),
+            "moves": result,
+            "time": start.elapsed().as_millis(),
+        }),
+    )
+}
+
+/// Reads the method, path and body of an HTTP request.
+fn read_request(stream: &TcpStream) -> Result<(String, String, String), Response> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .map_err(|_| error(400, "unreadable request"))?;
+
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(error(400, "malformed request line")),
+    };
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader
+            .read_line(&mut header)
+            .map_err(|_| error(400, "unreadable headers"))?;
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| error(400, "invalid content length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(error(413, "request too large"));
+    }
+
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| error(400, "incomplete body"))?;
+    let body = String::from_utf8(body).map_err(|_| error(400, "body is not utf-8"))?;
+    Ok((method, path, body))
+}
+
+fn write_response(mut stream: TcpStream, (status, body): Response) {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let body = body.to_string();
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+}
+
+/// Serves the analysis endpoints on localhost until the process is killed.
+pub fn serve(port: u16) -> Result<(), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|err| format!("could not listen on port {port}: {err}"))?;
+    println!("serving on http://127.0.0.1:{port}");
+
+    let (server, jobs) = Server::new();
+    let server = Arc::new(server);
+
+    let worker = Arc::clone(&server);
+    thread::spawn(move || worker.work(jobs));
+
+    for stream in listener.incoming().flatten() {
+        let server = Arc::clone(&server);
+        thread::spawn(move || server.handle(stream));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_KINGS: &str = "8/8/8/8/8/8/8/8 w - - 0 1";
+
+    #[test]
+    fn rejects_positions_without_both_kings() {
+        let (server, _jobs) = Server::new();
+        let body = json!({ "fen": NO_KINGS, "depth": 1 }).to_string();
+        for path in ["/analyse", "/perft", "/legal-moves", "/eval"] {
+            assert_eq!(server.route("POST", path, &body).0, 400, "{path}");
+        }
+
+        // Antichess is played without royal kings.
+        let body = json!({ "fen": NO_KINGS, "variant": "antichess" }).to_string();
+        assert_eq!(server.route("POST", "/legal-moves", &body).0, 200);
+    }
+
+    #[test]
+    fn keeps_working_after_a_job_panics() {
+        let (server, jobs) = Server::new();
+        let server = Arc::new(server);
+        let worker = Arc::clone(&server);
+        thread::spawn(move || worker.work(jobs));
+
+        let board = Board::try_from_fen(NO_KINGS).unwrap();
+        assert_eq!(server.enqueue(board, Work::Perft { depth: 1 }).0, 500);
+        assert_eq!(
+            server.enqueue(Board::new(), Work::Perft { depth: 1 }).0,
+            200
+        );
+    }
+}
@@ -0,0 +1,212 @@
+pub const SPRT_ALPHA: f64 = 0.05;
+pub const SPRT_BETA: f64 = 0.05;
+
+/// The most games an SPRT match plays when no number of games is given.
+pub const SPRT_GAMES: usize = 20000;
+
+/// How many standard deviations the error bars of the Elo estimate span,
+/// which makes them a 95% confidence interval.
+const CONFIDENCE: f64 = 1.96;
+
+/// A loss, a draw and a win added to the samples of every LLR, so that a
+/// match that was all won, all drawn or all lost still has a variance and
+/// ends early instead of running to the last game.
+const PSEUDO_SAMPLES: [f64; 3] = [0.0, 0.5, 1.0];
+
+/// How the results are grouped before the statistics are computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtModel {
+    /// Every game on its own as a win, draw or loss.
+    Trinomial,
+    /// Every pair of games from the same opening, with the colours
+    /// swapped, as one of five pair scores. This takes out most of the
+    /// noise the openings bring in.
+    Pentanomial,
+}
+
+impl SprtModel {
+    pub fn from_name(name: &str) -> Option<SprtModel> {
+        match name.to_ascii_lowercase().as_str() {
+            "trinomial" => Some(SprtModel::Trinomial),
+            "pentanomial" => Some(SprtModel::Pentanomial),
+            _ => None,
+        }
+    }
+
+    /// The scores the statistics are computed on: the games, or the
+    /// average of every complete pair of games.
+    fn samples(&self, scores: &[f64]) -> Vec<f64> {
+        match self {
+            SprtModel::Trinomial => scores.to_vec(),
+            SprtModel::Pentanomial => scores
+                .chunks_exact(2)
+                .map(|pair| (pair[0] + pair[1]) / 2.0)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtStatus {
+    Continue,
+    /// The Elo difference is more likely `elo0` than `elo1`.
+    AcceptH0,
+    /// The Elo difference is more likely `elo1` than `elo0`.
+    AcceptH1,
+}
+
+/// A generalized sequential probability ratio test of H0: the Elo
+/// difference is `elo0`, against H1: it is `elo1`, with the error rates
+/// `alpha` and `beta`.
+#[derive(Debug, Clone, Copy)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    pub model: SprtModel,
+}
+
+/// The expected score of the engine that is `elo` stronger.
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// The Elo difference an expected score stands for, kept finite for
+/// matches that were all won or all lost.
+fn elo(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+/// The mean and variance of the samples.
+fn mean_variance(samples: &[f64]) -> (f64, f64) {
+    let count = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / count;
+    let variance = samples
+        .iter()
+        .map(|sample| (sample - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    (mean, variance)
+}
+
+impl Sprt {
+    /// The log-likelihood ratios below and above which H0 or H1 is
+    /// accepted.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// The log-likelihood ratio of the scores the engine made, in the
+    /// order they were played, using the normal approximation of the
+    /// GSPRT.
+    pub fn llr(&self, scores: &[f64]) -> f64 {
+        let samples = self.model.samples(scores);
+        if samples.is_empty() {
+            return 0.0;
+        }
+        let samples = [&samples[..], &PSEUDO_SAMPLES].concat();
+        let (mean, variance) = mean_variance(&samples);
+
+        let score0 = expected_score(self.elo0);
+        let score1 = expected_score(self.elo1);
+        samples.len() as f64 * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    pub fn status(&self, scores: &[f64]) -> SprtStatus {
+        let llr = self.llr(scores);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtStatus::AcceptH1
+        } else if llr <= lower {
+            SprtStatus::AcceptH0
+        } else {
+            SprtStatus::Continue
+        }
+    }
+
+    /// The Elo difference the scores show and the error bars around it.
+    pub fn elo(&self, scores: &[f64]) -> (f64, f64) {
+        let samples = self.model.samples(scores);
+        if samples.is_empty() {
+            return (0.0, 0.0);
+        }
+        let (mean, variance) = mean_variance(&samples);
+        let error = CONFIDENCE * (variance / samples.len() as f64).sqrt();
+        let (low, high) = (elo(mean - error), elo(mean + error));
+        (elo(mean), (high - low) / 2.0)
+    }
+
+    /// The summary of the test after the scores, e.g.
+    /// `LLR: 1.23 (-2.94, 2.94) [0.00, 5.00], Elo: 12.3 +/- 20.1`.
+    pub fn report(&self, scores: &[f64]) -> String {
+        let (lower, upper) = self.bounds();
+        let (elo, error) = self.elo(scores);
+        format!(
+            "LLR: {:.2} ({lower:.2}, {upper:.2}) [{:.2}, {:.2}], Elo: {elo:.1} +/- {error:.1}",
+            self.llr(scores),
+            self.elo0,
+            self.elo1
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprt(model: SprtModel) -> Sprt {
+        Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: SPRT_ALPHA,
+            beta: SPRT_BETA,
+            model,
+        }
+    }
+
+    /// The scores of `wins`, `draws` and `losses` games.
+    fn games(wins: usize, draws: usize, losses: usize) -> Vec<f64> {
+        [vec![1.0; wins], vec![0.5; draws], vec![0.0; losses]].concat()
+    }
+
+    #[test]
+    fn llr_of_trinomial_results() {
+        // 420 wins, 960 draws and 380 losses give an LLR of 0.866 without
+        // the pseudo games.
+        let llr = sprt(SprtModel::Trinomial).llr(&games(420, 960, 380));
+        assert!((llr - 0.866).abs() < 0.01, "{llr}");
+    }
+
+    #[test]
+    fn llr_of_pentanomial_results() {
+        // 20, 150, 400, 180 and 30 pairs scoring 0, 0.5, 1, 1.5 and 2 give an
+        // LLR of 1.652 without the pseudo games.
+        let pairs = [
+            (20, [0.0, 0.0]),
+            (150, [0.5, 0.0]),
+            (400, [1.0, 0.0]),
+            (180, [1.0, 0.5]),
+            (30, [1.0, 1.0]),
+        ];
+        let scores = pairs
+            .iter()
+            .flat_map(|(count, pair)| pair.repeat(*count))
+            .collect::<Vec<_>>();
+        let llr = sprt(SprtModel::Pentanomial).llr(&scores);
+        assert!((llr - 1.652).abs() < 0.03, "{llr}");
+    }
+
+    #[test]
+    fn lopsided_matches_end_early() {
+        let sprt = sprt(SprtModel::Trinomial);
+        assert_eq!(sprt.status(&[]), SprtStatus::Continue);
+        assert_eq!(sprt.status(&games(40, 0, 0)), SprtStatus::AcceptH1);
+        assert_eq!(sprt.status(&games(0, 0, 40)), SprtStatus::AcceptH0);
+        assert_eq!(sprt.status(&games(0, 300, 0)), SprtStatus::AcceptH0);
+    }
+}
@@ -0,0 +1,370 @@
+use crate::board::{Board, CastlingRights, Color};
+use crate::endgame::KNOWN_WIN;
+use crate::eval::Eval;
+use crate::search::SearchMove;
+use crate::variant::Variant;
+
+use shakmaty::{
+    Bitboard, ByColor, ByRole, CastlingMode, Chess, FromSetup, PositionError, Setup, Square,
+};
+use shakmaty_syzygy::Tablebase;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The outcome stored in a WDL table for the side to move. Cursed wins and
+/// blessed losses are only decided by the 50-move rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl From<shakmaty_syzygy::Wdl> for Wdl {
+    fn from(wdl: shakmaty_syzygy::Wdl) -> Wdl {
+        match wdl {
+            shakmaty_syzygy::Wdl::Loss => Wdl::Loss,
+            shakmaty_syzygy::Wdl::BlessedLoss => Wdl::BlessedLoss,
+            shakmaty_syzygy::Wdl::Draw => Wdl::Draw,
+            shakmaty_syzygy::Wdl::CursedWin => Wdl::CursedWin,
+            shakmaty_syzygy::Wdl::Win => Wdl::Win,
+        }
+    }
+}
+
+/// The Syzygy tables found in `SyzygyPath` and how the search should probe
+/// them. The tables are only opened when they are first probed.
+pub struct Tablebases {
+    tables: Tablebase<Chess>,
+    found: usize,
+    probe_depth: u16,
+    probe_limit: u32,
+    fifty_move_rule: bool,
+}
+
+static TABLEBASES: Mutex<Option<Arc<Tablebases>>> = Mutex::new(None);
+static TB_HITS: AtomicUsize = AtomicUsize::new(0);
+
+/// Finds the tables in the directories of `path`, separated like `PATH`,
+/// and how the search should probe them. Returns the number of table files
+/// found.
+pub fn set_path(
+    path: &str,
+    probe_depth: u16,
+    probe_limit: u32,
+    fifty_move_rule: bool,
+) -> Result<usize, String> {
+    *TABLEBASES.lock().unwrap() = None;
+    if path.is_empty() || path == "<empty>" {
+        return Ok(0);
+    }
+
+    let tablebases = Tablebases::open(path, probe_depth, probe_limit, fifty_move_rule)?;
+    let found = tablebases.found;
+    *TABLEBASES.lock().unwrap() = Some(Arc::new(tablebases));
+    Ok(found)
+}
+
+/// The tables of `SyzygyPath`. A search takes them once when it starts, so
+/// the nodes probe them without locking.
+pub fn tablebases() -> Option<Arc<Tablebases>> {
+    TABLEBASES.lock().unwrap().clone()
+}
+
+pub fn reset_tb_hits() {
+    TB_HITS.store(0, Ordering::Relaxed);
+}
+
+pub fn tb_hits() -> usize {
+    TB_HITS.load(Ordering::Relaxed)
+}
+
+impl Tablebases {
+    pub fn open(
+        path: &str,
+        probe_depth: u16,
+        probe_limit: u32,
+        fifty_move_rule: bool,
+    ) -> Result<Tablebases, String> {
+        let mut tables = Tablebase::new();
+        let mut found = 0;
+
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        for directory in path.split(separator) {
+            found += tables
+                .add_directory(directory)
+                .map_err(|error| format!("Could not read \"{directory}\": {error}"))?;
+        }
+
+        Ok(Tablebases {
+            tables,
+            found,
+            probe_depth,
+            probe_limit,
+            fifty_move_rule,
+        })
+    }
+
+    /// The most pieces of any table found, at most `SyzygyProbeLimit`.
+    pub fn max_pieces(&self) -> u32 {
+        u32::min(self.tables.max_pieces() as u32, self.probe_limit)
+    }
+
+    /// Whether the tables can hold `board`: a standard chess position
+    /// without castling rights and with no more pieces than the largest
+    /// table.
+    fn covers(&self, board: &Board) -> bool {
+        (board.white_pieces | board.black_pieces).count_ones() <= self.max_pieces()
+            && board.variant == Variant::Standard
+            && board.castling_rights == CastlingRights::new()
+    }
+
+    /// The outcome of `board`, which has to be reached by a capture or a
+    /// pawn move for the 50-move rule to be counted right.
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        if !self.covers(board) {
+            return None;
+        }
+        let position = board.to_syzygy()?;
+        let wdl = self.tables.probe_wdl_after_zeroing(&position).ok()?;
+        Some(wdl.into())
+    }
+
+    /// The distance in plies to the next capture or pawn move of the best
+    /// line, positive if the side to move wins and negative if it loses.
+    pub fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        if !self.covers(board) {
+            return None;
+        }
+        let position = board.to_syzygy()?;
+        let dtz = self.tables.probe_dtz(&position).ok()?;
+        Some(dtz.ignore_rounding().0)
+    }
+
+    /// How good the position after a root move is for the side that made
+    /// it: the outcome from -2 for a loss to 2 for a win, and then how soon
+    /// a win zeroes the 50-move counter or how late a loss does.
+    fn root_rank(&self, board: &mut Board) -> Option<(i32, i32)> {
+        if board.generate_moves().is_empty() && board.is_check() {
+            return Some((2, 0));
+        }
+
+        // The distance is from the point of view of the opponent.
+        let dtz = self.probe_dtz(board)?;
+        let plies = dtz.unsigned_abs() + board.half_move_clock as u32;
+        let cursed = self.fifty_move_rule && plies > 100;
+        Some(match dtz.signum() {
+            -1 => (if cursed { 1 } else { 2 }, dtz.abs()),
+            1 => (if cursed { -1 } else { -2 }, -dtz),
+            _ => (0, 0),
+        })
+    }
+
+    /// The moves of `moves` that keep the best outcome the tables give
+    /// `board`, the ones that win the quickest first. None if `board` or a
+    /// position after one of the moves is missing from the tables.
+    pub fn root_moves(&self, board: &mut Board, moves: &[SearchMove]) -> Option<Vec<SearchMove>> {
+        if !self.covers(board) {
+            return None;
+        }
+
+        let mut ranked = Vec::new();
+        for search_move in moves {
+            board.make_move(&search_move.mov);
+            let rank = self.root_rank(board);
+            board.unmake_move(&search_move.mov);
+            ranked.push((rank?, *search_move));
+        }
+        TB_HITS.fetch_add(ranked.len(), Ordering::Relaxed);
+
+        let best = ranked.iter().map(|((outcome, _), _)| *outcome).max()?;
+        ranked.retain(|((outcome, _), _)| *outcome == best);
+        ranked.sort_by_key(|((_, distance), _)| *distance);
+        Some(
+            ranked
+                .into_iter()
+                .map(|(_, search_move)| search_move)
+                .collect(),
+        )
+    }
+}
+
+impl Board {
+    /// The position in the form the tables are probed with, if it is a
+    /// legal chess position.
+    fn to_syzygy(&self) -> Option<Chess> {
+        let board = shakmaty::Board::try_from_bitboards(
+            ByRole {
+                pawn: Bitboard(self.pawns),
+                knight: Bitboard(self.knights),
+                bishop: Bitboard(self.bishops),
+                rook: Bitboard(self.rooks),
+                queen: Bitboard(self.queens),
+                king: Bitboard(self.kings),
+            },
+            ByColor {
+                white: Bitboard(self.white_pieces),
+                black: Bitboard(self.black_pieces),
+            },
+        )
+        .ok()?;
+
+        let setup = Setup {
+            board,
+            turn: match self.turn {
+                Color::White => shakmaty::Color::White,
+                _ => shakmaty::Color::Black,
+            },
+            // The en passant square is set after every double push, even
+            // when no pawn can take.
+            ep_square: (self.en_passant_target != -1)
+                .then(|| Square::new(self.en_passant_target as u32)),
+            halfmoves: self.half_move_clock as u32,
+            ..Setup::empty()
+        };
+        Chess::from_setup(setup, CastlingMode::Standard)
+            .or_else(PositionError::ignore_invalid_ep_square)
+            .ok()
+    }
+
+    /// The tablebase score of the position inside the search, only probed
+    /// right after a capture or pawn move once the remaining depth is at
+    /// least `SyzygyProbeDepth`.
+    pub fn probe_tablebases(&self, tablebases: &Tablebases, depth: u16) -> Option<Eval> {
+        if depth < tablebases.probe_depth || self.half_move_clock != 0 {
+            return None;
+        }
+
+        let wdl = tablebases.probe_wdl(self)?;
+        TB_HITS.fetch_add(1, Ordering::Relaxed);
+
+        let score = match (wdl, tablebases.fifty_move_rule) {
+            (Wdl::Win, _) | (Wdl::CursedWin, false) => KNOWN_WIN as i64 * 2,
+            (Wdl::Loss, _) | (Wdl::BlessedLoss, false) => -KNOWN_WIN as i64 * 2,
+            (Wdl::CursedWin, true) => 1,
+            (Wdl::BlessedLoss, true) => -1,
+            (Wdl::Draw, _) => 0,
+        };
+        Some(Eval::from(score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The three and four piece tables the tests probe.
+    const TABLES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+
+    fn open(probe_limit: u32) -> Tablebases {
+        Tablebases::open(TABLES, 1, probe_limit, true).unwrap()
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::try_from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn finds_the_tables() {
+        let tablebases = open(7);
+        assert_eq!(tablebases.found, 12);
+        assert_eq!(tablebases.max_pieces(), 4);
+    }
+
+    #[test]
+    fn probes_the_outcome_of_known_positions() {
+        let tablebases = open(7);
+        for (fen, wdl) in [
+            ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", Wdl::Win),
+            ("4k3/8/8/8/8/8/8/3QK3 b - - 0 1", Wdl::Loss),
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win),
+            ("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1", Wdl::Draw),
+            ("k7/8/K7/P7/8/8/8/8 w - - 0 1", Wdl::Draw),
+            ("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1", Wdl::Draw),
+            ("8/8/8/8/8/8/8/KN5k w - - 0 1", Wdl::Draw),
+        ] {
+            assert_eq!(tablebases.probe_wdl(&board(fen)), Some(wdl), "{fen}");
+        }
+    }
+
+    #[test]
+    fn probes_the_distance_to_zeroing() {
+        let tablebases = open(7);
+        // Qb8 mates at once.
+        assert_eq!(
+            tablebases.probe_dtz(&board("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1")),
+            Some(1)
+        );
+        assert_eq!(
+            tablebases.probe_dtz(&board("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1")),
+            Some(0)
+        );
+    }
+
+    /// The five piece tables are too big to ship with the tests, so this
+    /// one runs with `cargo test -- --ignored` once `SYZYGY_PATH` holds at
+    /// least KRPvKR.
+    #[test]
+    #[ignore = "needs the KRPvKR tables in SYZYGY_PATH"]
+    fn probes_five_piece_tables() {
+        let path = std::env::var("SYZYGY_PATH").expect("SYZYGY_PATH is not set");
+        let tablebases = Tablebases::open(&path, 1, 7, true).unwrap();
+        assert!(tablebases.max_pieces() >= 5);
+
+        // The side to move takes a hanging rook.
+        for fen in [
+            "4k3/8/8/3r4/8/8/P7/3RK3 w - - 0 1",
+            "3rk3/p7/8/8/3R4/8/8/4K3 b - - 0 1",
+        ] {
+            assert_eq!(tablebases.probe_wdl(&board(fen)), Some(Wdl::Win), "{fen}");
+            assert_eq!(tablebases.probe_dtz(&board(fen)), Some(1), "{fen}");
+        }
+    }
+
+    #[test]
+    fn only_probes_up_to_the_probe_limit() {
+        let fen = "4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1";
+        assert_eq!(open(3).probe_wdl(&board(fen)), None);
+        assert_eq!(open(4).probe_wdl(&board(fen)), Some(Wdl::Draw));
+        // There is no table for five pieces.
+        assert_eq!(
+            open(7).probe_wdl(&board("4k3/4r3/8/8/8/8/3QR3/4K3 w - - 0 1")),
+            None
+        );
+        // Or for positions that can still castle.
+        assert_eq!(
+            open(7).probe_wdl(&board("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1")),
+            None
+        );
+    }
+
+    #[test]
+    fn keeps_the_root_moves_that_keep_the_win() {
+        let tablebases = open(7);
+        let mut board = board("8/8/8/8/8/2k5/8/3QK3 w - - 0 1");
+        let moves = board
+            .generate_moves()
+            .into_iter()
+            .map(|mov| SearchMove {
+                mov,
+                eval: Eval::from(0i64),
+            })
+            .collect::<Vec<_>>();
+        let kept = tablebases.root_moves(&mut board, &moves).unwrap();
+
+        assert!(!kept.is_empty() && kept.len() < moves.len());
+        for search_move in &kept {
+            board.make_move(&search_move.mov);
+            assert_eq!(tablebases.probe_dtz(&board).map(i32::signum), Some(-1));
+            board.unmake_move(&search_move.mov);
+        }
+        // The queen is lost after Qc2+ or Qd3+.
+        for mov in ["d1c2", "d1d3"] {
+            let mov = board.parse_move(mov).unwrap();
+            assert!(kept.iter().all(|search_move| search_move.mov != mov));
+        }
+    }
+}
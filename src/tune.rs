@@ -0,0 +1,208 @@
+use crate::board::Board;
+use crate::eval::{Score, MAX_PHASE};
+use crate::params::{Params, PARAMS};
+
+use rayon::prelude::*;
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+
+pub const TUNE_ITERATIONS: usize = 1000;
+/// Written to the working directory, to be copied over `src/weights.rs`
+/// once the new weights have proven themselves.
+pub const TUNE_OUTPUT: &str = "weights.rs";
+
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+/// A labelled position reduced to how every parameter moves its tapered
+/// evaluation, which is linear in the parameters.
+struct Entry {
+    result: f64,
+    constant: f64,
+    coefficients: Vec<(usize, f64)>,
+}
+
+/// Splits a line like `<fen> [0.5]` or `<fen> 1-0` into the fen and the
+/// result for white.
+fn parse_line(line: &str) -> Option<(&str, f64)> {
+    let line = line.trim().trim_end_matches(';');
+    let (fen, result) = line.rsplit_once(char::is_whitespace)?;
+    let result = match result.trim_matches(|c| c == '[' || c == ']' || c == '"') {
+        "1-0" | "1.0" | "1" => 1.0,
+        "1/2-1/2" | "0.5" => 0.5,
+        "0-1" | "0.0" | "0" => 0.0,
+        _ => return None,
+    };
+    Some((fen.trim().trim_end_matches(';'), result))
+}
+
+/// The untapered evaluation from white's point of view.
+fn total(board: &Board, params: &Params) -> Score {
+    board
+        .eval_terms(params)
+        .iter()
+        .fold(Score::default(), |total, (_, white, black)| {
+            total + *white - *black
+        })
+}
+
+fn taper(score: Score, phase: f64) -> f64 {
+    score.mg as f64 * phase + score.eg as f64 * (1.0 - phase)
+}
+
+fn extract(board: &Board, result: f64) -> Entry {
+    let vector = PARAMS.to_vector();
+    let base = total(board, &PARAMS);
+    let phase = i32::min(board.phase, MAX_PHASE) as f64 / MAX_PHASE as f64;
+
+    let mut coefficients = Vec::new();
+    let mut linear = 0.0;
+    for index in 0..vector.len() {
+        let mut changed = vector.clone();
+        changed[index] += 1;
+        let delta = total(board, &Params::from_vector(&changed)) - base;
+
+        // Even indices are midgame values, odd ones endgame values.
+        let coefficient = match index % 2 {
+            0 => delta.mg as f64 * phase,
+            _ => delta.eg as f64 * (1.0 - phase),
+        };
+        if coefficient != 0.0 {
+            coefficients.push((index, coefficient));
+            linear += coefficient * vector[index] as f64;
+        }
+    }
+
+    Entry {
+        result,
+        constant: taper(base, phase) - linear,
+        coefficients,
+    }
+}
+
+fn evaluate(entry: &Entry, params: &[f64]) -> f64 {
+    entry.constant
+        + entry
+            .coefficients
+            .iter()
+            .map(|(index, coefficient)| coefficient * params[*index])
+            .sum::<f64>()
+}
+
+fn sigmoid(eval: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+fn error(entries: &[Entry], params: &[f64], k: f64) -> f64 {
+    entries
+        .par_iter()
+        .map(|entry| (entry.result - sigmoid(evaluate(entry, params), k)).powi(2))
+        .sum::<f64>()
+        / entries.len() as f64
+}
+
+/// The scaling of the sigmoid that best fits the current evaluation.
+fn find_k(entries: &[Entry], params: &[f64]) -> f64 {
+    (1..=150)
+        .map(|step| step as f64 * 0.02)
+        .min_by(|a, b| {
+            error(entries, params, *a)
+                .partial_cmp(&error(entries, params, *b))
+                .unwrap()
+        })
+        .unwrap()
+}
+
+fn gradient(entries: &[Entry], params: &[f64], k: f64) -> Vec<f64> {
+    let scale = -2.0 * k * 10f64.ln() / 400.0 / entries.len() as f64;
+
+    entries
+        .par_iter()
+        .fold(
+            || vec![0.0; params.len()],
+            |mut gradient, entry| {
+                let sigmoid = sigmoid(evaluate(entry, params), k);
+                let factor = (entry.result - sigmoid) * sigmoid * (1.0 - sigmoid) * scale;
+                for (index, coefficient) in &entry.coefficients {
+                    gradient[*index] += factor * coefficient;
+                }
+                gradient
+            },
+        )
+        .reduce(
+            || vec![0.0; params.len()],
+            |a, b| a.iter().zip(b).map(|(a, b)| a + b).collect(),
+        )
+}
+
+fn load_entries(path: &str) -> Result<Vec<Entry>, String> {
+    let file = File::open(path).map_err(|error| format!("Could not open \"{path}\": {error}"))?;
+
+    let lines = BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .collect::<Vec<_>>();
+
+    lines
+        .par_iter()
+        .filter_map(|line| parse_line(line))
+        .map(|(fen, result)| Ok(extract(&Board::try_from_fen(fen)?, result)))
+        .collect()
+}
+
+/// Texel tuning: fits the evaluation parameters to the results of the games
+/// the positions in `path` come from with Adam, then writes them as the
+/// contents of `weights.rs` to `output`.
+pub fn tune(path: &str, iterations: usize, output: &str) -> Result<(), String> {
+    let entries = load_entries(path)?;
+    if entries.is_empty() {
+        return Err(format!("\"{path}\" has no labelled positions!"));
+    }
+    println!("Loaded {} positions", entries.len());
+
+    let mut params = PARAMS
+        .to_vector()
+        .iter()
+        .map(|value| *value as f64)
+        .collect::<Vec<_>>();
+
+    let k = find_k(&entries, &params);
+    println!("K = {k:.2}, error = {:.6}", error(&entries, &params, k));
+
+    let mut momentum = vec![0.0; params.len()];
+    let mut velocity = vec![0.0; params.len()];
+    for iteration in 1..=iterations {
+        let gradient = gradient(&entries, &params, k);
+
+        for index in 0..params.len() {
+            momentum[index] = BETA1 * momentum[index] + (1.0 - BETA1) * gradient[index];
+            velocity[index] =
+                BETA2 * velocity[index] + (1.0 - BETA2) * gradient[index] * gradient[index];
+
+            let momentum = momentum[index] / (1.0 - BETA1.powi(iteration as i32));
+            let velocity = velocity[index] / (1.0 - BETA2.powi(iteration as i32));
+            params[index] -= LEARNING_RATE * momentum / (velocity.sqrt() + 1e-8);
+        }
+
+        if iteration % 50 == 0 || iteration == iterations {
+            println!(
+                "Iteration {iteration}, error = {:.6}",
+                error(&entries, &params, k)
+            );
+        }
+    }
+
+    let tuned = Params::from_vector(
+        &params
+            .iter()
+            .map(|value| value.round() as i32)
+            .collect::<Vec<_>>(),
+    );
+    fs::write(output, tuned.as_rust())
+        .map_err(|error| format!("Could not write \"{output}\": {error}"))?;
+    println!("Wrote the tuned weights to \"{output}\"");
+    Ok(())
+}
+
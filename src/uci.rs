@@ -1,69 +1,177 @@
+use crate::bench::{bench, BENCH_DEPTH};
 use crate::board::Board;
-use crate::r#move::Move;
-use crate::search::Stopper;
+use crate::nnue;
+use crate::options::{Options, OPTIONS};
+use crate::polyglot;
+use crate::search::{SearchLimits, SharedTranspositionTable};
+use crate::search_controller::SearchController;
+use crate::syzygy;
+use crate::variant::Variant;
 
-use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
-use std::thread;
+use std::process::exit;
 
-use std::iter::from_fn;
+fn debug_log(debug: bool, message: String) {
+    if debug {
+        println!("info string {message}");
+    }
+}
 
-use std::process::exit;
+/// Applies the options that change the rules `board` is played by.
+fn apply_board_options(board: &mut Board, options: &Options) {
+    board.chess960 = options.check("UCI_Chess960");
+    board.variant = Variant::from_name(&options.string("UCI_Variant"));
+}
 
-pub fn handle_input(input: String, mut board: Board, stopper: &Stopper) -> Board {
-    let mut input = input[..input.len() - 1].split(" ").peekable();
-    match input.next().unwrap() {
+pub fn handle_input(
+    input: String,
+    mut board: Board,
+    options: &mut Options,
+    transposition_table: &SharedTranspositionTable,
+    controller: &mut SearchController,
+    debug: &mut bool,
+) -> Board {
+    let mut input = input.split_whitespace().peekable();
+    let command = match input.next() {
+        Some(command) => command,
+        None => return board,
+    };
+
+    match command {
         "uci" => {
             println!("id name chess_v4");
             println!("id author Hugo Lindström");
+            for option in OPTIONS.iter() {
+                println!("{}", option.as_uci());
+            }
             println!("uciok");
         }
         "isready" => println!("readyok"),
+        "debug" => match input.next() {
+            Some("on") => *debug = true,
+            Some("off") => *debug = false,
+            Some(argument) => println!("\"{argument}\" is not a valid argument to \"debug\"!"),
+            None => println!("\"debug\" needs on or off!"),
+        },
+        "ucinewgame" => {
+            controller.stop();
+            transposition_table.read().unwrap().clear();
+            board = Board::new();
+            apply_board_options(&mut board, options);
+            debug_log(*debug, "cleared the transposition table".into());
+        }
+        "setoption" => {
+            if input.next() != Some("name") {
+                println!("\"setoption\" needs a name!");
+                return board;
+            }
+
+            let mut name = Vec::new();
+            while let Some(word) = input.next_if(|word| *word != "value") {
+                name.push(word);
+            }
+            let name = name.join(" ");
+
+            input.next();
+            let value = input.collect::<Vec<_>>().join(" ");
+
+            match options.set(&name, &value) {
+                Ok("Hash") => {
+                    controller.stop();
+                    transposition_table
+                        .write()
+                        .unwrap()
+                        .resize(options.spin("Hash") as usize);
+                }
+                Ok("Clear Hash") => transposition_table.read().unwrap().clear(),
+                Ok("EvalFile") => {
+                    controller.stop();
+                    if let Err(error) = nnue::set_network(&options.string("EvalFile")) {
+                        println!("info string {error}");
+                    }
+                    board.nnue_init();
+                    match board.nnue {
+                        Some(_) => println!("info string using the network"),
+                        None => println!("info string using the handcrafted evaluation"),
+                    }
+                }
+                Ok("SyzygyPath" | "SyzygyProbeDepth" | "SyzygyProbeLimit" | "Syzygy50MoveRule") => {
+                    controller.stop();
+                    match syzygy::set_path(
+                        &options.string("SyzygyPath"),
+                        options.spin("SyzygyProbeDepth") as u16,
+                        options.spin("SyzygyProbeLimit") as u32,
+                        options.check("Syzygy50MoveRule"),
+                    ) {
+                        Ok(found) => println!("info string found {found} tablebases"),
+                        Err(error) => println!("info string {error}"),
+                    }
+                }
+                Ok("BookFile") => match polyglot::set_book(&options.string("BookFile")) {
+                    Ok(entries) => println!("info string loaded {entries} book entries"),
+                    Err(error) => println!("info string {error}"),
+                },
+                Ok("UCI_Chess960" | "UCI_Variant") => apply_board_options(&mut board, options),
+                Ok(_) => (),
+                Err(error) => println!("{error}"),
+            }
+            debug_log(*debug, format!("setoption {name} = \"{value}\""));
+        }
         "position" => {
-            match input.next().unwrap() {
-                "startpos" => board = Board::new(),
-                "fen" => {
-                    let mut count = 0;
-                    let fen = from_fn(|| {
-                        if count < 6 {
-                            count += 1;
-                            input.next()
-                        } else {
-                            None
+            let mut new_board = match input.next() {
+                Some("startpos") => Board::new(),
+                Some("fen") => {
+                    let mut fen = Vec::new();
+                    while let Some(part) = input.next_if(|part| *part != "moves") {
+                        fen.push(part);
+                    }
+                    match Board::try_from_fen(&fen.join(" ")) {
+                        Ok(new_board) => new_board,
+                        Err(error) => {
+                            println!("{error}");
+                            return board;
                         }
-                    })
-                    .collect::<Vec<_>>()
-                    .join(" ");
-                    board = Board::from_fen(fen.clone());
+                    }
                 }
-                argument => {
+                Some(argument) => {
                     println!("\"{argument}\" is not a valid argument to \"position\"!");
                     return board;
                 }
+                None => {
+                    println!("\"position\" needs startpos or fen!");
+                    return board;
+                }
+            };
+            apply_board_options(&mut new_board, options);
+            if !new_board.has_kings() {
+                println!("\"{}\" needs one king of each colour!", new_board.fen);
+                return board;
             }
+            board = new_board;
 
-            if input.peek().is_some() {
-                if input.next().unwrap() == "moves" {
-                    for mov in input {
-                        board.make_move(&Move::from_string(mov.into()));
+            if input.next() == Some("moves") {
+                for mov in input {
+                    match board.parse_move(mov) {
+                        Some(legal_move) => board.make_move(&legal_move),
+                        None => {
+                            println!("\"{mov}\" is not a legal move!");
+                            break;
+                        }
                     }
                 }
             }
+            debug_log(
+                *debug,
+                format!("position set, zobrist key {:016x}", board.zobrist),
+            );
         }
         "go" => {
-            let mut wtime = None;
-            let mut btime = None;
-            let mut winc = None;
-            let mut binc = None;
-            let mut moves_to_go = None;
-            let mut depth = None;
-            let mut nodes = None;
-            let mut mate = None;
-            let mut movetime = None;
+            let mut limits = SearchLimits::default();
 
             while input.peek().is_some() {
                 match input.next().unwrap() {
+                    "ponder" => limits.ponder = true,
                     "perft" => match input.next() {
                         Some(string) => match string.parse() {
                             Ok(depth) => {
@@ -76,7 +184,11 @@ pub fn handle_input(input: String, mut board: Board, stopper: &Stopper) -> Board
                                         .collect::<Vec<_>>()
                                         .join("\n")
                                 );
-                                println!("Nodes searched: {}\n", result.values().sum::<i32>());
+                                println!(
+                                    "Nodes searched: {}\n",
+                                    result.values().map(|&nodes| nodes as u64).sum::<u64>()
+                                );
+                                return board;
                             }
                             Err(_) => {
                                 println!("\"{string}\" is not a valid number!");
@@ -91,7 +203,7 @@ pub fn handle_input(input: String, mut board: Board, stopper: &Stopper) -> Board
                     "depth" => match input.next() {
                         Some(string) => match string.parse() {
                             Ok(value) => {
-                                depth = Some(value);
+                                limits.depth = Some(value);
                             }
                             Err(_) => {
                                 println!("\"{string}\" is not a valid number!");
@@ -106,7 +218,7 @@ pub fn handle_input(input: String, mut board: Board, stopper: &Stopper) -> Board
                     "wtime" => match input.next() {
                         Some(string) => match string.parse() {
                             Ok(value) => {
-                                wtime = Some(value);
+                                limits.wtime = Some(value);
                             }
                             Err(_) => {
                                 println!("\"{string}\" is not a valid number!");
@@ -121,7 +233,7 @@ pub fn handle_input(input: String, mut board: Board, stopper: &Stopper) -> Board
                     "btime" => match input.next() {
                         Some(string) => match string.parse() {
                             Ok(value) => {
-                                btime = Some(value);
+                                limits.btime = Some(value);
                             }
                             Err(_) => {
                                 println!("\"{string}\" is not a valid number!");
@@ -136,7 +248,7 @@ pub fn handle_input(input: String, mut board: Board, stopper: &Stopper) -> Board
                     "winc" => match input.next() {
                         Some(string) => match string.parse() {
                             Ok(value) => {
-                                winc = Some(value);
+                                limits.winc = Some(value);
                             }
                             Err(_) => {
                                 println!("\"{string}\" is not a valid number!");
@@ -151,7 +263,7 @@ pub fn handle_input(input: String, mut board: Board, stopper: &Stopper) -> Board
                     "binc" => match input.next() {
                         Some(string) => match string.parse() {
                             Ok(value) => {
-                                binc = Some(value);
+                                limits.binc = Some(value);
                             }
                             Err(_) => {
                                 println!("\"{string}\" is not a valid number!");
@@ -166,7 +278,7 @@ pub fn handle_input(input: String, mut board: Board, stopper: &Stopper) -> Board
                     "movetime" => match input.next() {
                         Some(string) => match string.parse() {
                             Ok(value) => {
-                                movetime = Some(value);
+                                limits.movetime = Some(value);
                             }
                             Err(_) => {
                                 println!("\"{string}\" is not a valid number!");
@@ -178,44 +290,109 @@ pub fn handle_input(input: String, mut board: Board, stopper: &Stopper) -> Board
                             return board;
                         }
                     },
+                    "movestogo" => match input.next() {
+                        Some(string) => match string.parse() {
+                            Ok(value) => {
+                                limits.moves_to_go = Some(value);
+                            }
+                            Err(_) => {
+                                println!("\"{string}\" is not a valid number!");
+                                return board;
+                            }
+                        },
+                        None => {
+                            println!("\"movestogo\" param needs a movestogo!");
+                            return board;
+                        }
+                    },
+                    "nodes" => match input.next() {
+                        Some(string) => match string.parse() {
+                            Ok(value) => {
+                                limits.nodes = Some(value);
+                            }
+                            Err(_) => {
+                                println!("\"{string}\" is not a valid number!");
+                                return board;
+                            }
+                        },
+                        None => {
+                            println!("\"nodes\" param needs a nodes!");
+                            return board;
+                        }
+                    },
+                    "mate" => match input.next() {
+                        Some(string) => match string.parse() {
+                            Ok(value) => {
+                                limits.mate = Some(value);
+                            }
+                            Err(_) => {
+                                println!("\"{string}\" is not a valid number!");
+                                return board;
+                            }
+                        },
+                        None => {
+                            println!("\"mate\" param needs a mate!");
+                            return board;
+                        }
+                    },
+                    "infinite" => limits.infinite = true,
                     argument => {
                         println!("\"{argument}\" is not implemented!");
                         return board;
                     }
                 }
             }
-            let mut board = board.clone();
-            let stopper = Arc::clone(stopper);
-            thread::spawn(move || {
-                let result = board.search(
-                    wtime,
-                    btime,
-                    winc,
-                    binc,
-                    moves_to_go,
-                    depth,
-                    nodes,
-                    mate,
-                    movetime,
-                    &stopper,
-                )[0]
-                .clone();
+            debug_log(*debug, format!("go {limits:?}"));
 
-                if result.eval.mate.is_some() {
-                    println!("info score mate {}", result.eval.mate.unwrap());
-                } else {
-                    println!("info score cp {}", result.eval.score);
+            if options.check("OwnBook") && !limits.ponder && board.variant == Variant::Standard {
+                let mov = polyglot::book()
+                    .and_then(|book| book.pick(&board, options.check("BookBestMove")));
+                if let Some(mov) = mov {
+                    // The previous search has to report its move first.
+                    controller.stop();
+                    println!("bestmove {}", board.move_to_uci(&mov));
+                    return board;
                 }
+            }
 
-                match result.mov.as_string().as_str() {
-                    "`1`1" => println!("Did not find any legal moves!"),
-                    mov => println!("bestmove {mov}"),
-                }
-            });
+            controller.start(
+                board.clone(),
+                limits,
+                options.clone(),
+                Arc::clone(transposition_table),
+            );
         }
+        "bench" => {
+            let depth = match input.next() {
+                Some(string) => match string.parse() {
+                    Ok(depth) => depth,
+                    Err(_) => {
+                        println!("\"{string}\" is not a valid number!");
+                        return board;
+                    }
+                },
+                None => BENCH_DEPTH,
+            };
+            controller.stop();
+            bench(depth);
+        }
+        "eval" => {
+            print!("{}", board.eval_trace());
+            let moves = board.generate_moves().len();
+            println!(
+                "Static evaluation: {} (side to move)",
+                board.eval(moves).as_uci()
+            );
+        }
+        "ponderhit" => controller.ponderhit(),
         "print_board" => board.print_board(),
-        "stop" => stopper.store(true, Ordering::SeqCst),
-        "quit" => exit(0),
+        "stop" => {
+            controller.stop();
+        }
+        "quit" => {
+            controller.stop();
+            exit(0);
+        }
         command => println!("\"{command}\" is not implemented!"),
     }
 
@@ -0,0 +1,248 @@
+use crate::board::{BitOperations, Bitmap, Board, Color, SquareOperations};
+use crate::eval::{Eval, Score};
+use crate::r#move::Move;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::sync::OnceLock;
+
+/// The rules the board is played by, chosen with `UCI_Variant`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Standard,
+    Crazyhouse,
+    ThreeCheck,
+    KingOfTheHill,
+    RacingKings,
+    Atomic,
+    Antichess,
+}
+
+/// The names of the variants as `UCI_Variant` lists them, in the order of
+/// `Variant`.
+pub const VARIANTS: [&str; 7] = [
+    "chess",
+    "crazyhouse",
+    "3check",
+    "kingofthehill",
+    "racingkings",
+    "atomic",
+    "antichess",
+];
+
+/// The third check wins in three-check.
+pub const MAX_CHECKS: u8 = 3;
+
+const CENTRE: Bitmap = 0x0000001818000000;
+const RANK_8: Bitmap = 0xFF00000000000000;
+
+const CHECK_SEED: u64 = 0x5468726565436865;
+
+const CHECK_BONUS: Score = Score::new(150, 150);
+const CENTRE_BONUS: Score = Score::new(40, 60);
+const RACE_BONUS: Score = Score::new(50, 60);
+
+impl Variant {
+    pub fn from_name(name: &str) -> Variant {
+        match VARIANTS
+            .iter()
+            .position(|variant| variant.eq_ignore_ascii_case(name))
+        {
+            Some(1) => Variant::Crazyhouse,
+            Some(2) => Variant::ThreeCheck,
+            Some(3) => Variant::KingOfTheHill,
+            Some(4) => Variant::RacingKings,
+            Some(5) => Variant::Atomic,
+            Some(6) => Variant::Antichess,
+            _ => Variant::Standard,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        VARIANTS[*self as usize]
+    }
+
+    /// Whether moves are generated without regard for the own king and
+    /// only kept if they turn out legal once played.
+    pub fn pseudo_legal(&self) -> bool {
+        matches!(self, Variant::Atomic | Variant::Antichess)
+    }
+
+    /// Whether each side has exactly one king, which the search and the
+    /// move generation rely on. In antichess the king is just another piece.
+    pub fn royal_kings(&self) -> bool {
+        *self != Variant::Antichess
+    }
+}
+
+/// Zobrist keys for giving one more check, by colour and how many checks
+/// were given before.
+fn check_keys() -> &'static [[u64; MAX_CHECKS as usize]; 2] {
+    static CHECK_KEYS: OnceLock<[[u64; MAX_CHECKS as usize]; 2]> = OnceLock::new();
+    CHECK_KEYS.get_or_init(|| {
+        let mut rng = StdRng::seed_from_u64(CHECK_SEED);
+        let mut keys = [[0; MAX_CHECKS as usize]; 2];
+        for key in keys.iter_mut().flatten() {
+            *key = rng.gen();
+        }
+        keys
+    })
+}
+
+/// The index into `Board::checks` of the side that just moved.
+fn mover_index(turn: Color) -> usize {
+    match turn {
+        Color::White => 1,
+        Color::Black => 0,
+        Color::Empty => unreachable!(),
+    }
+}
+
+/// How far `square` is from the nearest of the four centre squares.
+fn centre_distance(square: i32) -> i32 {
+    let file = square.file() as i32;
+    let rank = square.rank() as i32;
+    i32::max(
+        i32::max(3 - file, file - 4).max(0),
+        i32::max(3 - rank, rank - 4).max(0),
+    )
+}
+
+impl Board {
+    /// Whether the position has the kings its variant needs.
+    pub fn has_kings(&self) -> bool {
+        !self.variant.royal_kings()
+            || ((self.kings & self.white_pieces).count_ones() == 1
+                && (self.kings & self.black_pieces).count_ones() == 1)
+    }
+
+    /// The part of the hash that comes from the checks given.
+    pub fn check_zobrist(&self) -> u64 {
+        let mut zobrist = 0;
+        for (index, checks) in self.checks.iter().enumerate() {
+            for count in 0..*checks {
+                zobrist ^= check_keys()[index][count as usize];
+            }
+        }
+        zobrist
+    }
+
+    /// Counts the check the move that was just made gave, in three-check.
+    pub fn count_check(&mut self) {
+        let index = mover_index(self.turn);
+        if self.is_check() && self.checks[index] < MAX_CHECKS {
+            self.zobrist ^= check_keys()[index][self.checks[index] as usize];
+            self.checks[index] += 1;
+        }
+    }
+
+    /// Takes back `count_check`, before the move is unmade.
+    pub fn uncount_check(&mut self) {
+        let index = mover_index(self.turn);
+        if self.is_check() && self.checks[index] > 0 {
+            self.checks[index] -= 1;
+            self.zobrist ^= check_keys()[index][self.checks[index] as usize];
+        }
+    }
+
+    /// Racing kings forbids every move that gives check.
+    pub fn remove_checking_moves(&mut self, moves: &mut Vec<Move>) {
+        moves.retain(|mov| {
+            self.make_move(mov);
+            let check = self.is_check();
+            self.unmake_move(mov);
+            !check
+        });
+    }
+
+    /// The result for the side to move when the game is over by the rules
+    /// of the variant rather than by mate or stalemate.
+    pub fn variant_result(&mut self) -> Option<Eval> {
+        // Mates are counted in plies, an odd count being a win for the side
+        // to move.
+        let lost = Eval {
+            score: 0,
+            mate: Some(0),
+        };
+        let won = Eval {
+            score: 0,
+            mate: Some(1),
+        };
+        let enemy_king = self.enemy_pieces() & self.kings;
+
+        match self.variant {
+            Variant::ThreeCheck if self.checks[mover_index(self.turn)] >= MAX_CHECKS => Some(lost),
+            Variant::KingOfTheHill if enemy_king & CENTRE > 0 => Some(lost),
+            Variant::Atomic if self.own_pieces() & self.kings == 0 => Some(lost),
+            Variant::Antichess if self.own_pieces() == 0 => Some(won),
+            Variant::RacingKings if self.kings & RANK_8 > 0 => {
+                let own_king = self.own_pieces() & self.kings;
+                if own_king & RANK_8 > 0 && enemy_king & RANK_8 > 0 {
+                    return Some(Eval::from(0i64));
+                }
+                if own_king & RANK_8 > 0 {
+                    return Some(won);
+                }
+
+                // White moves first, so black may still draw by reaching
+                // the last rank right after.
+                if self.turn == Color::Black {
+                    let mut moves = self.generate_king_moves();
+                    self.remove_checking_moves(&mut moves);
+                    if moves.iter().any(|mov| (1 << mov.end_square) & RANK_8 > 0) {
+                        return None;
+                    }
+                }
+                Some(lost)
+            }
+            _ => None,
+        }
+    }
+
+    /// What the variant adds to the evaluation, from white's point of view.
+    pub fn variant_score(&self) -> Score {
+        let white_king = (self.white_pieces & self.kings).lsb();
+        let black_king = (self.black_pieces & self.kings).lsb();
+
+        match self.variant {
+            Variant::ThreeCheck => CHECK_BONUS * (self.checks[0] as i32 - self.checks[1] as i32),
+            Variant::KingOfTheHill => {
+                CENTRE_BONUS * (centre_distance(black_king) - centre_distance(white_king))
+            }
+            Variant::RacingKings => {
+                RACE_BONUS * (white_king.rank() as i32 - black_king.rank() as i32)
+            }
+            _ => Score::default(),
+        }
+    }
+
+    /// The checks left to each side in the fen of three-check, e.g. `3+2`.
+    pub fn checks_fen(&self) -> String {
+        format!(
+            "{}+{}",
+            MAX_CHECKS - self.checks[0],
+            MAX_CHECKS - self.checks[1]
+        )
+    }
+
+    /// Reads the checks of three-check, either left as in `3+2` or given as
+    /// in `+0+1`.
+    pub fn parse_checks(&mut self, checks: &str) {
+        let (given, counts) = match checks.strip_prefix('+') {
+            Some(counts) => (true, counts),
+            None => (false, checks),
+        };
+        let counts = counts
+            .split('+')
+            .map(|count| count.parse::<u8>().unwrap_or(0).min(MAX_CHECKS))
+            .collect::<Vec<_>>();
+
+        for (index, count) in counts.into_iter().take(2).enumerate() {
+            self.checks[index] = match given {
+                true => count,
+                false => MAX_CHECKS - count,
+            };
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// Generated by `chess_v4 tune`, edit by hand or tune again.
+pub const PARAMS: Params = Params {
+    material: [
+        Score::new(82, 94),
+        Score::new(337, 281),
+        Score::new(365, 297),
+        Score::new(477, 512),
+        Score::new(1025, 936),
+        Score::new(0, 0),
+    ],
+    passed_pawn: [
+        Score::new(0, 0),
+        Score::new(2, 8),
+        Score::new(5, 12),
+        Score::new(10, 22),
+        Score::new(25, 45),
+        Score::new(50, 90),
+        Score::new(90, 150),
+        Score::new(0, 0),
+    ],
+    blocked_passed_pawn: [
+        Score::new(0, 0),
+        Score::new(1, 4),
+        Score::new(3, 6),
+        Score::new(5, 10),
+        Score::new(12, 20),
+        Score::new(25, 40),
+        Score::new(45, 70),
+        Score::new(0, 0),
+    ],
+    candidate_passer: [
+        Score::new(0, 0),
+        Score::new(1, 3),
+        Score::new(2, 5),
+        Score::new(5, 10),
+        Score::new(10, 20),
+        Score::new(20, 35),
+        Score::new(0, 0),
+        Score::new(0, 0),
+    ],
+    isolated_pawn: Score::new(-10, -15),
+    doubled_pawn: Score::new(-10, -25),
+    backward_pawn: Score::new(-8, -10),
+    supported_pawn: Score::new(8, 6),
+    phalanx_pawn: Score::new(5, 3),
+    pawn_shield: [
+        Score::new(20, 0),
+        Score::new(10, 0),
+    ],
+    missing_shield_pawn: Score::new(-15, 0),
+    pawn_storm: [
+        Score::new(0, 0),
+        Score::new(-5, 0),
+        Score::new(-20, 0),
+        Score::new(-12, 0),
+        Score::new(-5, 0),
+    ],
+    open_file_near_king: Score::new(-25, 0),
+    half_open_file_near_king: Score::new(-12, 0),
+    mobility: [
+        Score::new(0, 0),
+        Score::new(4, 4),
+        Score::new(5, 5),
+        Score::new(2, 4),
+        Score::new(1, 2),
+        Score::new(0, 0),
+    ],
+    bishop_pair: Score::new(30, 50),
+    rook_open_file: Score::new(25, 10),
+    rook_semi_open_file: Score::new(12, 6),
+    rook_on_seventh: Score::new(10, 20),
+    knight_outpost: Score::new(25, 10),
+    bishop_outpost: Score::new(12, 5),
+    trapped_bishop: Score::new(-50, -50),
+    trapped_rook: Score::new(-40, -5),
+};
@@ -0,0 +1,337 @@
+use crate::board::{Board, Color};
+use crate::options::Options;
+use crate::r#move::Move;
+use crate::search::{Protocol, SearchLimits, SharedTranspositionTable};
+use crate::search_controller::SearchController;
+use crate::variant::Variant;
+
+use std::sync::{Arc, Mutex};
+
+use std::process::exit;
+
+/// The variants xboard may ask for that start from the standard position,
+/// by their xboard name and their `UCI_Variant` name.
+const XBOARD_VARIANTS: [(&str, &str); 5] = [
+    ("normal", "chess"),
+    ("crazyhouse", "crazyhouse"),
+    ("3check", "3check"),
+    ("kingofthehill", "kingofthehill"),
+    ("atomic", "atomic"),
+];
+
+/// What xboard has told the engine that outlasts a single command.
+pub struct Xboard {
+    /// The game, which the search thread plays the engine's move on as
+    /// soon as it is found.
+    game: Arc<Mutex<Board>>,
+    /// In force mode moves are only played, never answered.
+    force: bool,
+    /// The side the engine plays when it is not in force mode.
+    engine: Color,
+    post: bool,
+    moves_per_session: u64,
+    /// The base time and increment of the `level` command in milliseconds.
+    base: u64,
+    increment: u64,
+    move_time: Option<u64>,
+    depth: Option<u16>,
+    /// The clocks of the engine and its opponent in milliseconds, as the
+    /// last `time` and `otim` commands gave them.
+    time: Option<u64>,
+    opponent_time: Option<u64>,
+}
+
+impl Xboard {
+    pub fn new() -> Self {
+        Xboard {
+            game: Arc::new(Mutex::new(Board::new())),
+            force: false,
+            engine: Color::Black,
+            post: false,
+            moves_per_session: 40,
+            base: 300_000,
+            increment: 0,
+            move_time: None,
+            depth: None,
+            time: None,
+            opponent_time: None,
+        }
+    }
+
+    /// The limits of a search for the engine's move in `board`.
+    fn limits(&self, board: &Board) -> SearchLimits {
+        let mut limits = SearchLimits {
+            depth: self.depth,
+            protocol: Protocol::Xboard { post: self.post },
+            ..Default::default()
+        };
+
+        if self.move_time.is_some() {
+            limits.movetime = self.move_time;
+            return limits;
+        }
+
+        let time = self.time.unwrap_or(self.base);
+        let opponent_time = self.opponent_time.unwrap_or(self.base);
+        (limits.wtime, limits.btime) = match board.turn {
+            Color::White => (Some(time), Some(opponent_time)),
+            _ => (Some(opponent_time), Some(time)),
+        };
+        limits.winc = Some(self.increment);
+        limits.binc = Some(self.increment);
+
+        if self.moves_per_session > 0 {
+            let played = (board.irreversible.len() / 2) as u64;
+            let left = self.moves_per_session - played % self.moves_per_session;
+            limits.moves_to_go = Some(left.min(u8::MAX as u64) as u8);
+        }
+        limits
+    }
+
+    /// Starts thinking on the engine's move, or claims the result if the
+    /// game is already over. The move is played and the result claimed on
+    /// the search thread, so they are never late for the next command.
+    fn think(
+        &self,
+        board: &mut Board,
+        options: &Options,
+        transposition_table: &SharedTranspositionTable,
+        controller: &mut SearchController,
+    ) {
+        if claim_result(board) {
+            return;
+        }
+
+        let game = Arc::clone(&self.game);
+        controller.start_with(
+            board.clone(),
+            self.limits(board),
+            options.clone(),
+            Arc::clone(transposition_table),
+            move |_, mov| {
+                if *mov == Move::null() {
+                    return;
+                }
+
+                let mut board = game.lock().unwrap();
+                println!("move {}", board.move_to_uci(mov));
+                board.make_move(mov);
+                claim_result(&mut board);
+            },
+        );
+    }
+}
+
+/// Claims the result if the game is over, returns whether it is.
+fn claim_result(board: &mut Board) -> bool {
+    match board.game_over() {
+        Some((result, reason)) => {
+            println!("{} {{{reason}}}", result.as_pgn());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Takes back the last move, which only works as far back as the last
+/// `new` or `setboard`.
+fn undo(board: &mut Board) {
+    match board.irreversible.last() {
+        Some(last) => {
+            let mov = last.mov;
+            board.unmake_move(&mov);
+        }
+        None => println!("Error (no move to undo): undo"),
+    }
+}
+
+/// Reads the minutes of `level`, either as `5` or as `0:30`.
+fn parse_minutes(base: &str) -> Option<u64> {
+    let (minutes, seconds) = base.split_once(':').unwrap_or((base, "0"));
+    Some(minutes.parse::<u64>().ok()? * 60_000 + seconds.parse::<u64>().ok()? * 1000)
+}
+
+/// Reads a number of centiseconds as milliseconds.
+fn parse_centiseconds(time: Option<&str>) -> Option<u64> {
+    time?
+        .parse::<i64>()
+        .ok()
+        .map(|time| time.max(0) as u64 * 10)
+}
+
+pub fn handle_input(
+    input: String,
+    xboard: &mut Xboard,
+    options: &Options,
+    transposition_table: &SharedTranspositionTable,
+    controller: &mut SearchController,
+) {
+    let mut input = input.split_whitespace();
+    let command = match input.next() {
+        Some(command) => command,
+        None => return,
+    };
+
+    // Commands about the clocks and settings are answered while the engine
+    // thinks. `?` wants the move now, every other command changes the game,
+    // so the move being searched for is no longer wanted.
+    match command {
+        "xboard" | "protover" | "accepted" | "rejected" | "random" | "hard" | "easy"
+        | "computer" | "name" | "rating" | "." | "ping" | "level" | "st" | "sd" | "time"
+        | "otim" | "post" | "nopost" => (),
+        "?" => controller.stop(),
+        _ => controller.abort(),
+    }
+
+    let game = Arc::clone(&xboard.game);
+    let mut board = game.lock().unwrap();
+
+    match command {
+        "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer" | "name"
+        | "rating" | "?" | "." => (),
+        "protover" => {
+            let variants = XBOARD_VARIANTS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(",");
+            println!("feature myname=\"chess_v4\" setboard=1 usermove=1 ping=1 playother=1");
+            println!("feature sigint=0 sigterm=0 colors=0 variants=\"{variants}\"");
+            println!("feature done=1");
+        }
+        "ping" => println!("pong {}", input.next().unwrap_or("")),
+        "new" => {
+            transposition_table.read().unwrap().clear();
+            *board = Board::new();
+            xboard.force = false;
+            xboard.engine = Color::Black;
+            xboard.depth = None;
+            xboard.time = None;
+            xboard.opponent_time = None;
+        }
+        "variant" => {
+            let name = input.next().unwrap_or("");
+            match XBOARD_VARIANTS.iter().find(|(xboard, _)| *xboard == name) {
+                Some((_, variant)) => board.variant = Variant::from_name(variant),
+                None => println!("Error (unsupported variant): {name}"),
+            }
+        }
+        "force" | "result" => xboard.force = true,
+        "go" => {
+            xboard.force = false;
+            xboard.engine = board.turn;
+            xboard.think(&mut board, options, transposition_table, controller);
+        }
+        "playother" => {
+            xboard.force = false;
+            xboard.engine = match board.turn {
+                Color::White => Color::Black,
+                _ => Color::White,
+            };
+        }
+        "level" => {
+            let moves = input.next().and_then(|moves| moves.parse().ok());
+            let base = input.next().and_then(parse_minutes);
+            let increment = input
+                .next()
+                .and_then(|increment| increment.parse::<f64>().ok());
+            match (moves, base, increment) {
+                (Some(moves), Some(base), Some(increment)) => {
+                    xboard.moves_per_session = moves;
+                    xboard.base = base;
+                    xboard.increment = (increment * 1000.0) as u64;
+                    xboard.move_time = None;
+                }
+                _ => println!("Error (invalid time control): level"),
+            }
+        }
+        "st" => match input.next().and_then(|time| time.parse::<f64>().ok()) {
+            Some(time) => xboard.move_time = Some((time * 1000.0) as u64),
+            None => println!("Error (invalid time): st"),
+        },
+        "sd" => match input.next().and_then(|depth| depth.parse().ok()) {
+            Some(depth) => xboard.depth = Some(depth),
+            None => println!("Error (invalid depth): sd"),
+        },
+        "time" => xboard.time = parse_centiseconds(input.next()),
+        "otim" => xboard.opponent_time = parse_centiseconds(input.next()),
+        "post" => xboard.post = true,
+        "nopost" => xboard.post = false,
+        "undo" => undo(&mut board),
+        "remove" => {
+            undo(&mut board);
+            undo(&mut board);
+        }
+        "setboard" => {
+            let fen = input.collect::<Vec<_>>().join(" ");
+            let mut new_board = match Board::try_from_fen(&fen) {
+                Ok(board) => board,
+                Err(_) => {
+                    println!("tellusererror Illegal position");
+                    return;
+                }
+            };
+            if new_board.variant == Variant::Standard {
+                new_board.variant = board.variant;
+            }
+            if !new_board.has_kings() {
+                println!("tellusererror Illegal position");
+                return;
+            }
+            *board = new_board;
+        }
+        "quit" => exit(0),
+        command => {
+            // Without usermove=1 the moves come on their own.
+            let mov = match command {
+                "usermove" => input.next().unwrap_or(""),
+                mov => mov,
+            };
+            match board.parse_move(mov) {
+                Some(legal_move) => board.make_move(&legal_move),
+                None if command == "usermove" => {
+                    println!("Illegal move: {mov}");
+                    return;
+                }
+                None => {
+                    println!("Error (unknown command): {command}");
+                    return;
+                }
+            }
+
+            if !xboard.force && board.turn == xboard.engine {
+                xboard.think(&mut board, options, transposition_table, controller);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::TranspositionTable;
+    use std::sync::RwLock;
+
+    #[test]
+    fn level_sets_the_clock_and_the_increment() {
+        let mut xboard = Xboard::new();
+        let options = Options::new();
+        let transposition_table = Arc::new(RwLock::new(TranspositionTable::new(1)));
+        let mut controller = SearchController::new();
+        handle_input(
+            "level 0 2 12".to_string(),
+            &mut xboard,
+            &options,
+            &transposition_table,
+            &mut controller,
+        );
+
+        let limits = xboard.limits(&Board::new());
+        assert_eq!(limits.wtime, Some(120_000));
+        assert_eq!(limits.btime, Some(120_000));
+        assert_eq!(limits.winc, Some(12_000));
+        assert_eq!(limits.binc, Some(12_000));
+        assert_eq!(limits.moves_to_go, None);
+        assert_eq!(limits.movetime, None);
+    }
+}